@@ -0,0 +1,237 @@
+//! Actions on clones.
+//!
+//! An action replaces or removes a clone, and never touches the original.
+//! Each replacing action is atomic: we create the link at a temporary
+//! path in the same directory, then rename it over the clone.
+
+use std::{env,fs,io};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+use crate::args::Args;
+
+/// What to do with a clone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    None,
+    Delete,
+    Recycle,
+    Shred,
+    Symlink,
+    Hardlink,
+}
+
+impl Default for Action {
+    fn default() -> Self {
+        Action::None
+    }
+}
+
+impl Action {
+
+    /// Get the action chosen by the args.
+    ///
+    /// A script from `--emit-script` deletes clones, unless another action is chosen.
+    pub fn from_args(args: &Args) -> Self {
+        if args.delete { return Action::Delete }
+        if args.recycle { return Action::Recycle }
+        if args.shred { return Action::Shred }
+        if args.symlink { return Action::Symlink }
+        if args.hardlink { return Action::Hardlink }
+        if args.emit_script.is_some() { return Action::Delete }
+        Action::None
+    }
+
+    /// Apply the action to a clone of an original.
+    ///
+    /// A clone that is already a hardlink of the original is left as is by
+    /// `Action::Hardlink`, because renaming a link over another link of the
+    /// same inode does nothing, and would leave the temporary path behind.
+    pub fn apply<T: AsRef<Path>>(self, original: T, clone: T) -> io::Result<()> {
+        let (original, clone) = (original.as_ref(), clone.as_ref());
+        if original == clone {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "action: original is clone"))
+        }
+        match self {
+            Action::None => Ok(()),
+            Action::Delete => fs::remove_file(clone),
+            Action::Recycle => recycle(clone),
+            Action::Shred => shred(original, clone),
+            Action::Symlink => replace(clone, |tmp| std::os::unix::fs::symlink(fs::canonicalize(original)?, tmp)),
+            Action::Hardlink => {
+                if same_file::is_same_file(original, clone)? { return Ok(()) }
+                replace(clone, |tmp| fs::hard_link(original, tmp))
+            },
+        }
+    }
+
+}
+
+/// Replace a file atomically, by creating a temporary path then renaming it.
+fn replace<F: FnOnce(&Path) -> io::Result<()>>(path: &Path, create: F) -> io::Result<()> {
+    let tmp = tmp_path(path)?;
+    create(&tmp)?;
+    fs::rename(&tmp, path).inspect_err(|_| { let _ = fs::remove_file(&tmp); })
+}
+
+/// Get the temporary path for replacing a file, which is a hidden
+/// sibling, so the rename stays on the same file system.
+pub fn tmp_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "replace: no file name"))?;
+    let mut tmp_name = std::ffi::OsString::from(".");
+    tmp_name.push(name);
+    tmp_name.push(".deduplicate-tmp");
+    Ok(path.with_file_name(tmp_name))
+}
+
+/// Shred a file by overwriting its content with zeros, then removing it.
+///
+/// If the clone shares its inode with the original, then overwriting
+/// would destroy the original, so we only remove the clone's link.
+fn shred(original: &Path, clone: &Path) -> io::Result<()> {
+    if same_file::is_same_file(original, clone)? {
+        return fs::remove_file(clone)
+    }
+    let len = fs::metadata(clone)?.len();
+    let mut file = OpenOptions::new().write(true).open(clone)?;
+    let zeros = [0u8; 8192];
+    let mut n: u64 = 0;
+    while n < len {
+        let k = std::cmp::min(zeros.len() as u64, len - n) as usize;
+        file.write_all(&zeros[..k])?;
+        n += k as u64;
+    }
+    file.sync_all()?;
+    fs::remove_file(clone)
+}
+
+/// Recycle a file by moving it to the trash, per the freedesktop.org trash spec.
+///
+/// The trash is `$XDG_DATA_HOME/Trash` or `~/.local/share/Trash`.
+fn recycle(path: &Path) -> io::Result<()> {
+    let trash = trash_dir()?;
+    let files = trash.join("files");
+    let info = trash.join("info");
+    fs::create_dir_all(&files)?;
+    fs::create_dir_all(&info)?;
+    let absolute = fs::canonicalize(path)?;
+    let name = path.file_name().ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "recycle: no file name"))?;
+    let mut trash_name = PathBuf::from(name);
+    let mut i = 1;
+    while files.join(&trash_name).exists() || info.join(format!("{}.trashinfo", trash_name.display())).exists() {
+        trash_name = PathBuf::from(format!("{}.{}", Path::new(name).display(), i));
+        i += 1;
+    }
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    fs::write(
+        info.join(format!("{}.trashinfo", trash_name.display())),
+        format!("[Trash Info]\nPath={}\nDeletionDate={}\n", absolute.display(), crate::mtime::format_time(seconds)),
+    )?;
+    fs::rename(&absolute, files.join(&trash_name))
+}
+
+/// Get the trash directory.
+fn trash_dir() -> io::Result<PathBuf> {
+    if let Some(dir) = env::var_os("XDG_DATA_HOME") {
+        return Ok(PathBuf::from(dir).join("Trash"))
+    }
+    match env::var_os("HOME") {
+        Some(home) => Ok(PathBuf::from(home).join(".local").join("share").join("Trash")),
+        None => Err(io::Error::new(io::ErrorKind::NotFound, "recycle: no home")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    /// Create a temp dir with these files:
+    ///
+    /// * `alpha.txt` which contains `alpha`
+    /// * `alpha_2.txt` which contains `alpha`
+    ///
+    fn setup(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join("deduplicate-files-test-action").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("alpha.txt"), "alpha\n").unwrap();
+        fs::write(dir.join("alpha_2.txt"), "alpha\n").unwrap();
+        dir
+    }
+
+    #[test]
+    /// Test `Action::Delete`.
+    /// Must remove the clone and keep the original.
+    ///
+    fn test_apply_x_delete() {
+        let dir = setup("delete");
+        Action::Delete.apply(dir.join("alpha.txt"), dir.join("alpha_2.txt")).unwrap();
+        assert!(dir.join("alpha.txt").exists());
+        assert!(!dir.join("alpha_2.txt").exists());
+    }
+
+    #[test]
+    /// Test `Action::Hardlink`.
+    /// Must replace the clone with a hardlink to the original.
+    ///
+    fn test_apply_x_hardlink() {
+        let dir = setup("hardlink");
+        Action::Hardlink.apply(dir.join("alpha.txt"), dir.join("alpha_2.txt")).unwrap();
+        assert_eq!(fs::metadata(dir.join("alpha.txt")).unwrap().ino(), fs::metadata(dir.join("alpha_2.txt")).unwrap().ino());
+    }
+
+    #[test]
+    /// Test `Action::Hardlink` with a clone that is already a hardlink to the original.
+    /// Must keep the link, and leave no temporary path behind.
+    ///
+    fn test_apply_x_hardlink_x_hardlink() {
+        let dir = setup("hardlink_hardlink");
+        fs::remove_file(dir.join("alpha_2.txt")).unwrap();
+        fs::hard_link(dir.join("alpha.txt"), dir.join("alpha_2.txt")).unwrap();
+        Action::Hardlink.apply(dir.join("alpha.txt"), dir.join("alpha_2.txt")).unwrap();
+        assert_eq!(fs::metadata(dir.join("alpha.txt")).unwrap().ino(), fs::metadata(dir.join("alpha_2.txt")).unwrap().ino());
+        assert!(!tmp_path(&dir.join("alpha_2.txt")).unwrap().exists());
+    }
+
+    #[test]
+    /// Test `Action::Symlink`.
+    /// Must replace the clone with a symlink to the original.
+    ///
+    fn test_apply_x_symlink() {
+        let dir = setup("symlink");
+        Action::Symlink.apply(dir.join("alpha.txt"), dir.join("alpha_2.txt")).unwrap();
+        assert!(fs::symlink_metadata(dir.join("alpha_2.txt")).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read_to_string(dir.join("alpha_2.txt")).unwrap(), "alpha\n");
+    }
+
+    #[test]
+    /// Test `Action::Shred` with a clone that is a hardlink to the original.
+    /// Must keep the original content.
+    ///
+    fn test_apply_x_shred_hardlink() {
+        let dir = setup("shred_hardlink");
+        fs::remove_file(dir.join("alpha_2.txt")).unwrap();
+        fs::hard_link(dir.join("alpha.txt"), dir.join("alpha_2.txt")).unwrap();
+        Action::Shred.apply(dir.join("alpha.txt"), dir.join("alpha_2.txt")).unwrap();
+        assert_eq!(fs::read_to_string(dir.join("alpha.txt")).unwrap(), "alpha\n");
+        assert!(!dir.join("alpha_2.txt").exists());
+    }
+
+    #[test]
+    /// Test `apply` where the original is the clone.
+    /// Must err.
+    ///
+    fn test_apply_x_same_path() {
+        let dir = setup("same_path");
+        assert_eq!(
+            Action::Delete.apply(dir.join("alpha.txt"), dir.join("alpha.txt")).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput,
+        );
+        assert!(dir.join("alpha.txt").exists());
+    }
+
+}
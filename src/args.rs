@@ -1,11 +1,33 @@
-/// Args for the application. 
-///
-/// These args correspond to the matches in the file `clap.rs`.
-/// We have these args in their own file in order to be flexible,
-/// such as being able to start our app with other arg parsers.
+//! Args for the application.
+//!
+//! These args correspond to the matches in the file `clap.rs`.
+//! We have these args in their own file in order to be flexible,
+//! such as being able to start our app with other arg parsers.
 
 use std::default::Default;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::SystemTime;
+use crate::compare::Compare;
+use crate::device::ReadOrder;
+use crate::output::Format;
+use crate::read::ReadOptions;
+use crate::report::Report;
+use crate::summary::Counters;
+use crate::sync::Link;
+
+/// A subcommand, which runs instead of detecting duplicates.
+#[derive(Debug)]
+pub enum Subcommand {
+    /// Compare directory A with directory B by content.
+    Diff { a: PathBuf, b: PathBuf },
+    /// Copy content that is missing from the destination, and copy or link content that is there under another path.
+    Sync { src: PathBuf, dst: PathBuf, link: Link },
+    /// Write a manifest of the path, size, mtime, and digest of each file in the paths.
+    ManifestWrite { out: PathBuf, paths: Vec<PathBuf> },
+    /// Find each file in the paths whose content is in the manifest.
+    ManifestCompare { manifest: PathBuf, paths: Vec<PathBuf> },
+}
 
 #[derive(Default, Debug)]
 pub struct Args {
@@ -14,10 +36,39 @@ pub struct Args {
     pub(crate) find_cloned: bool,
     pub(crate) find_uniques: bool,
     pub(crate) print: bool,
+    pub(crate) print0: bool,
     pub(crate) delete: bool,
     pub(crate) recycle: bool,
     pub(crate) shred: bool,
     pub(crate) symlink: bool,
     pub(crate) hardlink: bool,
+    pub(crate) newer_than: Option<SystemTime>,
+    pub(crate) older_than: Option<SystemTime>,
+    pub(crate) clones_newer_than: Option<SystemTime>,
+    pub(crate) clones_older_than: Option<SystemTime>,
+    pub(crate) follow_symlinks: bool,
+    pub(crate) one_file_system: bool,
+    pub(crate) min_depth: Option<usize>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) skip_hidden: bool,
+    pub(crate) files_from: Option<PathBuf>,
+    pub(crate) hardlinks_deduplicated: bool,
+    pub(crate) jobs: usize,
+    pub(crate) jobs_per_device: usize,
+    pub(crate) read_order: ReadOrder,
+    pub(crate) read_options: ReadOptions,
+    pub(crate) compare: Compare,
+    pub(crate) verify: bool,
+    pub(crate) format: Format,
+    pub(crate) emit_script: Option<PathBuf>,
+    pub(crate) report: Option<(Report, PathBuf)>,
+    pub(crate) by_directory: bool,
+    pub(crate) directories: bool,
+    pub(crate) directories_ignore_names: bool,
+    pub(crate) subsets: bool,
+    pub(crate) subsets_min_overlap: f64,
+    pub(crate) reference: Vec<PathBuf>,
     pub(crate) paths: Vec<PathBuf>,
+    pub(crate) subcommand: Option<Subcommand>,
+    pub(crate) counters: Arc<Counters>,
 }
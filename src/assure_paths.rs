@@ -1,15 +1,12 @@
-use std::{fs,io};
-use std::fs::File;
-use std::io::prelude::*;
+use std::io;
 use std::path::Path;
-
-/// File buffer size fpr comparing bytes
-const FILE_BUFFER_SIZE: usize = 8192;
+use crate::read::{self, ReadOptions};
 
 /// Do two files have equal length?
+#[cfg(test)]
 pub fn assure_paths_metadata_len_eq<T: AsRef<Path>>(a: T, b: T) -> io::Result<bool> {
-    let a_metadata = fs::metadata(a)?;
-    let b_metadata = fs::metadata(b)?;
+    let a_metadata = std::fs::metadata(a)?;
+    let b_metadata = std::fs::metadata(b)?;
     match a_metadata.is_file() && b_metadata.is_file() {
         true => Ok(a_metadata.len() == b_metadata.len()),
         false => Err(
@@ -22,17 +19,34 @@ pub fn assure_paths_metadata_len_eq<T: AsRef<Path>>(a: T, b: T) -> io::Result<bo
 }
 
 /// Do two files have equal bytes?
+#[cfg(test)]
 pub fn assure_paths_read_bytes_eq<T: AsRef<Path>>(a: T, b: T) -> io::Result<bool> {
-    let mut a_file = File::open(a)?;
-    let mut b_file = File::open(b)?;
-    let mut a_buffer = [0; FILE_BUFFER_SIZE];
-    let mut b_buffer = [0; FILE_BUFFER_SIZE];
+    assure_paths_read_bytes_eq_with_options(a, b, &ReadOptions::default())
+}
+
+/// Do two files have equal bytes, when read with these options?
+///
+/// We fill each buffer fully before comparing, because a `read` can return
+/// fewer bytes than asked for, and the two files can return different counts.
+pub fn assure_paths_read_bytes_eq_with_options<T: AsRef<Path>>(a: T, b: T, options: &ReadOptions) -> io::Result<bool> {
+    let mut a_file = read::open_sequential(a)?;
+    let mut b_file = read::open_sequential(b)?;
+    if a_file.metadata()?.len() != b_file.metadata()?.len() { return Ok(false) }
+    if options.mmap {
+        return match (read::mmap(&a_file)?, read::mmap(&b_file)?) {
+            (Some(a_mmap), Some(b_mmap)) => Ok(a_mmap[..] == b_mmap[..]),
+            (None, None) => Ok(true),
+            _ => Ok(false),
+        }
+    }
+    let mut a_buffer = vec![0; options.buffer_size];
+    let mut b_buffer = vec![0; options.buffer_size];
     loop {
-        let a_n = a_file.read(&mut a_buffer)?;
-        let b_n = b_file.read(&mut b_buffer)?;
-        if a_n == 0 && b_n == 0 { return Ok(true) }
+        let a_n = read::read_full(&mut a_file, &mut a_buffer)?;
+        let b_n = read::read_full(&mut b_file, &mut b_buffer)?;
         if a_n != b_n { return Ok(false) }
-        if a_buffer[0..a_n] != b_buffer[0..b_n] { return Ok(false) }
+        if a_n == 0 { return Ok(true) }
+        if a_buffer[..a_n] != b_buffer[..b_n] { return Ok(false) }
     }
 }
 
@@ -49,7 +63,7 @@ mod tests {
     fn test_file_len_eq_x_equal() {
         let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_len_eq", "alpha.txt"].iter().collect();
         let b: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_len_eq", "bravo.txt"].iter().collect();
-        assert_eq!(file_len_eq(a, b).unwrap(), true);
+        assert_eq!(assure_paths_metadata_len_eq(a, b).unwrap(), true);
     }
 
     #[test]
@@ -59,7 +73,7 @@ mod tests {
     fn test_file_len_eq_x_inequal() {
         let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_len_eq", "alpha.txt"].iter().collect();
         let b: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_len_eq", "charlie.txt"].iter().collect();
-        assert_eq!(file_len_eq(a, b).unwrap(), false);
+        assert_eq!(assure_paths_metadata_len_eq(a, b).unwrap(), false);
     }
 
     #[test]
@@ -69,8 +83,8 @@ mod tests {
     fn test_file_len_eq_x_invalid_args() {
         let valid: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_len_eq", "alpha.txt"].iter().collect();
         let invalid: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_len_eq"].iter().collect(); // i.e. anything that's not a file
-        assert_eq!(file_len_eq(valid.clone(), invalid.clone()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
-        assert_eq!(file_len_eq(invalid.clone(), valid.clone()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(assure_paths_metadata_len_eq(valid.clone(), invalid.clone()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(assure_paths_metadata_len_eq(invalid.clone(), valid.clone()).unwrap_err().kind(), io::ErrorKind::InvalidInput);
     }
 
     #[test]
@@ -80,7 +94,7 @@ mod tests {
     fn test_file_bytes_eq_x_equal() {
         let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha.txt"].iter().collect();
         let b: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha_2.txt"].iter().collect();
-        assert_eq!(file_bytes_eq(a, b).unwrap(), true);
+        assert_eq!(assure_paths_read_bytes_eq(a, b).unwrap(), true);
     }
 
     #[test]
@@ -90,7 +104,25 @@ mod tests {
     fn test_file_bytes_eq_x_inequal() {
         let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha.txt"].iter().collect();
         let b: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "bravo.txt"].iter().collect();
-        assert_eq!(file_bytes_eq(a, b).unwrap(), false);
+        assert_eq!(assure_paths_read_bytes_eq(a, b).unwrap(), false);
+    }
+
+    #[test]
+    /// Test `assure_paths_read_bytes_eq_with_options` with a tiny buffer and with mmap.
+    /// Must be true for equal files, and false for inequal files.
+    ///
+    fn test_assure_paths_read_bytes_eq_with_options() {
+        let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha.txt"].iter().collect();
+        let b: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha_2.txt"].iter().collect();
+        let c: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "bravo.txt"].iter().collect();
+        for options in &[
+            ReadOptions { buffer_size: 1, mmap: false },
+            ReadOptions { buffer_size: 4, mmap: false },
+            ReadOptions { buffer_size: 4, mmap: true },
+        ] {
+            assert_eq!(assure_paths_read_bytes_eq_with_options(&a, &b, options).unwrap(), true);
+            assert_eq!(assure_paths_read_bytes_eq_with_options(&a, &c, options).unwrap(), false);
+        }
     }
 
 }
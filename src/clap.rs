@@ -9,24 +9,28 @@
 //! We favor our convention of doing clap setup in a file named `clap.rs`,
 //! rather than in `main.rs`, because we favor the separation of concerns.
 
-use clap::{Arg, ArgAction};
-use crate::args::Args;
-
+use clap::{Arg, Values};
 use std::path::PathBuf;
-use crate::args::Args;
+use crate::compare::Compare;
+use crate::device::ReadOrder;
+use crate::output::Format;
+use crate::read::ReadOptions;
+use crate::report::Report;
+use crate::sync::Link;
+use crate::args::{Args, Subcommand};
 
-/// Create a clap command.
-pub fn clap() -> crate::Args {
+/// Create a clap command, then parse the command line into args.
+pub fn args() -> Args {
     let matches = clap::command!()
     .name("deduplicate-files")
     .version("1.0.0")
     .author("Joel Parker Henderson <joel@joelparkerhenderson.com>")
-    .help("Helps with file synchronization, dedupilication, and more")
+    .about("Helps with file synchronization, dedupilication, and more")
     .arg(Arg::new("verbose")
         .help("Set the verbosity level")
         .short('v')
         .long("verbose")
-        .action(ArgAction::Count))
+        .multiple_occurrences(true))
     .arg(Arg::new("find-cloned")
         .help("Find files that are cloned i.e. originals (compare --find-clones, --find-uniques)")
         .long("find-cloned"))
@@ -37,8 +41,12 @@ pub fn clap() -> crate::Args {
         .help("Find files that are uniques i.e not cloned or clones (compare --find-cloned, --find-clones)")
         .long("find-uniques"))
     .arg(Arg::new("print")
-        .help("Print the results i.e. dry run")
+        .help("Print the results i.e. dry run; with a scan, needs --format text")
         .long("print"))
+    .arg(Arg::new("print0")
+        .help("Terminate each printed path with NUL instead of newline, e.g. for xargs -0")
+        .short('0')
+        .long("print0"))
     .arg(Arg::new("delete")
         .help("Delete clones immediately")
         .long("delete")
@@ -79,22 +87,259 @@ pub fn clap() -> crate::Args {
         .conflicts_with("recycle")
         .conflicts_with("shred")
         .conflicts_with("symlink"))
+    .arg(Arg::new("newer-than")
+        .help("Only scan files modified after this date or duration ago e.g. 2020-01-31 or 30d")
+        .long("newer-than")
+        .takes_value(true)
+        .validator(|s| crate::mtime::parse_time(s)))
+    .arg(Arg::new("older-than")
+        .help("Only scan files modified before this date or duration ago e.g. 2020-01-31 or 30d")
+        .long("older-than")
+        .takes_value(true)
+        .validator(|s| crate::mtime::parse_time(s)))
+    .arg(Arg::new("clones-newer-than")
+        .help("Only act on clones modified after this date or duration ago e.g. 2020-01-31 or 30d")
+        .long("clones-newer-than")
+        .takes_value(true)
+        .validator(|s| crate::mtime::parse_time(s)))
+    .arg(Arg::new("clones-older-than")
+        .help("Only act on clones modified before this date or duration ago e.g. 2020-01-31 or 90d")
+        .long("clones-older-than")
+        .takes_value(true)
+        .validator(|s| crate::mtime::parse_time(s)))
+    .arg(Arg::new("follow-symlinks")
+        .help("Follow symlinks while walking, with symlink loop detection")
+        .long("follow-symlinks"))
+    .arg(Arg::new("one-file-system")
+        .help("Stay on one file system i.e. do not cross mount points")
+        .long("one-file-system"))
+    .arg(Arg::new("min-depth")
+        .help("Skip entries that are fewer than this many levels below each path")
+        .long("min-depth")
+        .takes_value(true)
+        .validator(|s| s.parse::<usize>()))
+    .arg(Arg::new("max-depth")
+        .help("Skip entries that are more than this many levels below each path")
+        .long("max-depth")
+        .takes_value(true)
+        .validator(|s| s.parse::<usize>()))
+    .arg(Arg::new("skip-hidden")
+        .help("Skip hidden dot-files and dot-directories")
+        .long("skip-hidden"))
+    .arg(Arg::new("files-from")
+        .help("Read the files to consider from this file, or from stdin if `-`, separated by newline or NUL")
+        .long("files-from")
+        .takes_value(true))
+    .arg(Arg::new("hardlinks-deduplicated")
+        .help("Treat existing hardlinks as already deduplicated i.e. never clones of each other")
+        .long("hardlinks-deduplicated"))
+    .arg(Arg::new("jobs")
+        .help("Run this many worker threads for walking and hashing; 0 means one per CPU")
+        .short('j')
+        .long("jobs")
+        .takes_value(true)
+        .default_value("0")
+        .validator(|s| s.parse::<usize>()))
+    .arg(Arg::new("jobs-per-device")
+        .help("Read at most this many files at once per device; 0 means 1 for a spinning disk, and no limit otherwise")
+        .long("jobs-per-device")
+        .takes_value(true)
+        .default_value("0")
+        .validator(|s| s.parse::<usize>()))
+    .arg(Arg::new("read-order")
+        .help("Order reads on rotational disks by walk order, inode, or physical offset")
+        .long("read-order")
+        .takes_value(true)
+        .possible_values(&["walk", "inode", "offset"])
+        .default_value("walk"))
+    .arg(Arg::new("buffer-size")
+        .help("Read files with this buffer size e.g. 8192, 64K, 1M")
+        .long("buffer-size")
+        .takes_value(true)
+        .default_value("1M")
+        .validator(|s| crate::read::parse_size(s)))
+    .arg(Arg::new("mmap")
+        .help("Read files by memory-mapping them instead of by buffers")
+        .long("mmap"))
+    .arg(Arg::new("verify")
+        .help("Before acting on a clone, confirm that its bytes equal the original's, by reading both")
+        .long("verify"))
+    .arg(Arg::new("compare")
+        .help("Compare same-length files by digest, or by reading them all in lockstep so each file is read at most once")
+        .long("compare")
+        .takes_value(true)
+        .possible_values(&["digest", "lockstep"])
+        .default_value("digest"))
+    .arg(Arg::new("format")
+        .help("Output format: text, or JSON, or NDJSON i.e. one duplicate group per line, or CSV or TSV i.e. one file per row, or like fdupes, or like jdupes -j")
+        .long("format")
+        .takes_value(true)
+        .possible_values(&["text", "json", "ndjson", "csv", "tsv", "fdupes", "jdupes-json"])
+        .default_value("text"))
+    .arg(Arg::new("emit-script")
+        .help("Write a POSIX shell script of the action for each clone, to review and run, instead of acting; the action is delete unless chosen")
+        .long("emit-script")
+        .value_name("FILE")
+        .takes_value(true))
+    .arg(Arg::new("report")
+        .help("Write a report of duplicate groups to a file; the kind is html, for a self-contained page")
+        .long("report")
+        .value_names(&["KIND", "FILE"])
+        .number_of_values(2))
+    .arg(Arg::new("by-directory")
+        .help("Print clone bytes per directory, rolled up the tree like du, and directory pairs that share the most content, instead of the duplicates")
+        .long("by-directory"))
+    .arg(Arg::new("directories")
+        .help("Detect duplicate directories, whose whole subtrees are identical, and report them instead of the files under them; directories are reported only, and actions still apply to every file under a clone directory, via its file group")
+        .long("directories"))
+    .arg(Arg::new("directories-ignore-names")
+        .help("With --directories, match directories by the multiset of their file contents, regardless of names or layout, e.g. to find renamed copies")
+        .long("directories-ignore-names")
+        .requires("directories"))
+    .arg(Arg::new("subsets")
+        .help("Detect subset directories, whose files all have content-identical files in another directory, or partly, with the percent overlap")
+        .long("subsets"))
+    .arg(Arg::new("subsets-min-overlap")
+        .help("With --subsets, report a directory only if at least this percent of its files are in the other directory")
+        .long("subsets-min-overlap")
+        .value_name("PERCENT")
+        .takes_value(true)
+        .validator(|s| crate::subset::parse_percent(s))
+        .default_value("50"))
+    .arg(Arg::new("reference")
+        .help("Scan files within this path only as potential originals, which are never clones and never touched; repeatable")
+        .long("reference")
+        .value_name("PATH")
+        .takes_value(true)
+        .multiple_occurrences(true))
     .arg(Arg::new("paths")
         .help("Paths to process")
-        .min_values(0));
-    Args {
-        verbose: std::cmp::max(3, matches.occurrences_of("verbose") as u8),
+        .min_values(0))
+    .subcommand(clap::Command::new("diff")
+        .about("List files only in A, only in B, identical in both by content, and changed at the same path")
+        .arg(Arg::new("a")
+            .help("Directory A")
+            .required(true))
+        .arg(Arg::new("b")
+            .help("Directory B")
+            .required(true)))
+    .subcommand(clap::Command::new("sync")
+        .about("Copy files whose content is nowhere in the destination, and put in place content that is there under another path")
+        .arg(Arg::new("link")
+            .help("Put in place content that is in the destination under another path by copying from the source, or by hardlinking or reflinking the destination's file")
+            .long("link")
+            .takes_value(true)
+            .possible_values(&["copy", "hardlink", "reflink"])
+            .default_value("copy"))
+        .arg(Arg::new("src")
+            .help("Source directory")
+            .required(true))
+        .arg(Arg::new("dst")
+            .help("Destination directory")
+            .required(true)))
+    .subcommand(clap::Command::new("manifest")
+        .about("Write a manifest of files, or find files whose content is in a manifest, such as of an offline backup")
+        .subcommand_required(true)
+        .subcommand(clap::Command::new("write")
+            .about("Write the path, size, mtime, and digest of each file in the paths, one JSON object per line")
+            .arg(Arg::new("out")
+                .help("Manifest file to write")
+                .required(true))
+            .arg(Arg::new("paths")
+                .help("Paths to process")
+                .min_values(1)
+                .required(true)))
+        .subcommand(clap::Command::new("compare")
+            .about("Find each file in the paths whose content is in the manifest")
+            .arg(Arg::new("manifest")
+                .help("Manifest file to read")
+                .required(true))
+            .arg(Arg::new("paths")
+                .help("Paths to process")
+                .min_values(1)
+                .required(true))))
+    .get_matches();
+    let args = Args {
+        verbose: std::cmp::min(3, matches.occurrences_of("verbose") as u8),
         find_clones: matches.is_present("find-clones"),
         find_cloned: matches.is_present("find-cloned"),
         find_uniques: matches.is_present("find-uniques"),
         print: matches.is_present("print"),
+        print0: matches.is_present("print0"),
         delete: matches.is_present("delete"),
         recycle: matches.is_present("recycle"),
         shred: matches.is_present("shred"),
         symlink: matches.is_present("symlink"),
         hardlink: matches.is_present("hardlink"),
+        newer_than: matches.value_of("newer-than").map(|s| crate::mtime::parse_time(s).unwrap()),
+        older_than: matches.value_of("older-than").map(|s| crate::mtime::parse_time(s).unwrap()),
+        clones_newer_than: matches.value_of("clones-newer-than").map(|s| crate::mtime::parse_time(s).unwrap()),
+        clones_older_than: matches.value_of("clones-older-than").map(|s| crate::mtime::parse_time(s).unwrap()),
+        follow_symlinks: matches.is_present("follow-symlinks"),
+        one_file_system: matches.is_present("one-file-system"),
+        min_depth: matches.value_of("min-depth").map(|s| s.parse::<usize>().unwrap()),
+        max_depth: matches.value_of("max-depth").map(|s| s.parse::<usize>().unwrap()),
+        skip_hidden: matches.is_present("skip-hidden"),
+        files_from: matches.value_of("files-from").map(|x| PathBuf::from(x)),
+        hardlinks_deduplicated: matches.is_present("hardlinks-deduplicated"),
+        jobs: matches.value_of("jobs").map(|s| s.parse::<usize>().unwrap()).unwrap_or(0),
+        jobs_per_device: matches.value_of("jobs-per-device").map(|s| s.parse::<usize>().unwrap()).unwrap_or(0),
+        read_order: matches.value_of("read-order").map(|s| s.parse::<ReadOrder>().unwrap()).unwrap_or_default(),
+        read_options: ReadOptions {
+            buffer_size: matches.value_of("buffer-size").map(|s| crate::read::parse_size(s).unwrap()).unwrap_or(crate::read::DEFAULT_BUFFER_SIZE),
+            mmap: matches.is_present("mmap"),
+        },
+        compare: matches.value_of("compare").map(|s| s.parse::<Compare>().unwrap()).unwrap_or_default(),
+        verify: matches.is_present("verify"),
+        format: matches.value_of("format").map(|s| s.parse::<Format>().unwrap()).unwrap_or_default(),
+        emit_script: matches.value_of("emit-script").map(|x| PathBuf::from(x)),
+        report: matches.values_of("report").map(|mut x| {
+            let kind = x.next().unwrap();
+            let report = kind.parse::<Report>().unwrap_or_else(|e| { eprintln!("err:{} --report {}", e, kind); std::process::exit(2) });
+            (report, PathBuf::from(x.next().unwrap()))
+        }),
+        by_directory: matches.is_present("by-directory"),
+        directories: matches.is_present("directories"),
+        directories_ignore_names: matches.is_present("directories-ignore-names"),
+        subsets: matches.is_present("subsets"),
+        subsets_min_overlap: matches.value_of("subsets-min-overlap").map(|s| crate::subset::parse_percent(s).unwrap()).unwrap_or(50.0),
+        reference: matches.values_of("reference")
+        .unwrap_or_else(||Values::default())
+        .map(|x| PathBuf::from(x)).collect(),
         paths: matches.values_of("paths")
         .unwrap_or_else(||Values::default())
         .map(|x| PathBuf::from(x)).collect(),
+        subcommand: match matches.subcommand() {
+            Some(("diff", m)) => Some(Subcommand::Diff {
+                a: PathBuf::from(m.value_of("a").unwrap()),
+                b: PathBuf::from(m.value_of("b").unwrap()),
+            }),
+            Some(("sync", m)) => Some(Subcommand::Sync {
+                src: PathBuf::from(m.value_of("src").unwrap()),
+                dst: PathBuf::from(m.value_of("dst").unwrap()),
+                link: m.value_of("link").map(|s| s.parse::<Link>().unwrap()).unwrap_or_default(),
+            }),
+            Some(("manifest", m)) => match m.subcommand() {
+                Some(("write", m)) => Some(Subcommand::ManifestWrite {
+                    out: PathBuf::from(m.value_of("out").unwrap()),
+                    paths: m.values_of("paths").unwrap_or_default().map(|x| PathBuf::from(x)).collect(),
+                }),
+                Some(("compare", m)) => Some(Subcommand::ManifestCompare {
+                    manifest: PathBuf::from(m.value_of("manifest").unwrap()),
+                    paths: m.values_of("paths").unwrap_or_default().map(|x| PathBuf::from(x)).collect(),
+                }),
+                _ => None,
+            },
+            _ => None,
+        },
+        counters: Default::default(),
+    };
+    // `--print` writes each scanned path to stdout, which would corrupt a
+    // structured format; a diff or sync writes no scanned paths, so is fine.
+    let scans = matches!(args.subcommand, None | Some(Subcommand::ManifestWrite { .. }) | Some(Subcommand::ManifestCompare { .. }));
+    if args.print && scans && args.format != Format::Text {
+        eprintln!("err:--print needs --format text format:{:?}", args.format);
+        std::process::exit(2)
     }
+    args
 }
@@ -0,0 +1,167 @@
+//! The command line interface, which the binary is a thin shell over.
+
+use std::io;
+use std::time::Instant;
+use crate::{Tracker, on_files_from, on_group, on_paths, detect_duplicates};
+use crate::{clap, diff, manifest, output, report, script, subset, summary, sync, tree};
+use crate::action::Action;
+use crate::args::{Args, Subcommand};
+use crate::summary::Summary;
+
+/// Run a subcommand, then write its results to stdout.
+fn on_subcommand(args: &Args, subcommand: &Subcommand) {
+    match subcommand {
+        Subcommand::Diff { a, b } => {
+            let diff = diff::diff(args, a, b);
+            if let Err(e) = diff::write_diff(args, &diff, &mut io::stdout().lock()) {
+                error!(args.counters, "err:{} write_diff", e);
+            }
+        },
+        Subcommand::Sync { src, dst, link } => {
+            let diff = diff::diff(args, src, dst);
+            let steps = sync::sync(args, &diff, *link);
+            if let Err(e) = sync::write_steps(args, &steps, &mut io::stdout().lock()) {
+                error!(args.counters, "err:{} write_steps", e);
+            }
+        },
+        Subcommand::ManifestWrite { out, paths } => {
+            let mut tracker = Tracker::new();
+            on_paths(args, &mut tracker, paths);
+            let entries = manifest::entries(args, &tracker);
+            if let Err(e) = manifest::write_manifest(&entries, out) {
+                error!(args.counters, "err:{} write_manifest:{:?}", e, out.display());
+            }
+        },
+        Subcommand::ManifestCompare { manifest, paths } => {
+            let entries = match manifest::read_manifest(manifest) {
+                Ok(entries) => entries,
+                Err(e) => { error!(args.counters, "err:{} read_manifest:{:?}", e, manifest.display()); return },
+            };
+            let mut tracker = Tracker::new();
+            on_paths(args, &mut tracker, paths);
+            let matches = manifest::compare(args, &tracker, &entries);
+            if let Err(e) = manifest::write_matches(args, &matches, &mut io::stdout().lock()) {
+                error!(args.counters, "err:{} write_matches", e);
+            }
+        },
+    }
+}
+
+/// Run the command line interface: parse the args, then scan, detect, act,
+/// and write the results, or else run a subcommand.
+pub fn main() {
+    let args = clap::args();
+    if args.verbose > 1 {
+        println!("verbose level:{}", args.verbose);
+        if args.find_cloned { println!("--find-cloned"); }
+        if args.find_clones { println!("--find-clones"); }
+        if args.find_uniques { println!("--find-uniques"); }
+        if args.print { println!("--print"); }
+        if args.print0 { println!("--print0"); }
+        if args.delete { println!("--delete"); }
+        if args.recycle { println!("--recycle"); }
+        if args.shred { println!("--shred"); }
+        if args.symlink { println!("--symlink"); }
+        if args.hardlink { println!("--hardlink"); }
+        if let Some(x) = args.newer_than { println!("--newer-than {:?}", x); }
+        if let Some(x) = args.older_than { println!("--older-than {:?}", x); }
+        if let Some(x) = args.clones_newer_than { println!("--clones-newer-than {:?}", x); }
+        if let Some(x) = args.clones_older_than { println!("--clones-older-than {:?}", x); }
+        if args.follow_symlinks { println!("--follow-symlinks"); }
+        if args.one_file_system { println!("--one-file-system"); }
+        if let Some(x) = args.min_depth { println!("--min-depth {}", x); }
+        if let Some(x) = args.max_depth { println!("--max-depth {}", x); }
+        if args.skip_hidden { println!("--skip-hidden"); }
+        if let Some(x) = &args.files_from { println!("--files-from {:?}", x); }
+        if args.hardlinks_deduplicated { println!("--hardlinks-deduplicated"); }
+        println!("--jobs {}", args.jobs);
+        println!("--jobs-per-device {}", args.jobs_per_device);
+        println!("--read-order {:?}", args.read_order);
+        println!("--buffer-size {}", args.read_options.buffer_size);
+        println!("--compare {:?}", args.compare);
+        if args.read_options.mmap { println!("--mmap"); }
+        if args.verify { println!("--verify"); }
+        println!("--format {:?}", args.format);
+        if let Some(x) = &args.emit_script { println!("--emit-script {:?}", x); }
+        if let Some((x, y)) = &args.report { println!("--report {:?} {:?}", x, y); }
+        if args.by_directory { println!("--by-directory"); }
+        if args.directories { println!("--directories"); }
+        if args.directories_ignore_names { println!("--directories-ignore-names"); }
+        if args.subsets { println!("--subsets"); }
+        println!("--subsets-min-overlap {}", args.subsets_min_overlap);
+        for x in args.reference.iter() { println!("--reference {:?}", x); }
+        if let Some(x) = &args.subcommand { println!("subcommand {:?}", x); }
+    }
+
+    if let Err(e) = rayon::ThreadPoolBuilder::new().num_threads(args.jobs).build_global() {
+        error!(args.counters, "err:{} build_global", e);
+    }
+
+    if let Some(subcommand) = &args.subcommand {
+        on_subcommand(&args, subcommand);
+        return
+    }
+
+    let mut tracker = Tracker::new();
+
+    let start = Instant::now();
+    on_paths(&args, &mut tracker, &args.reference);
+    on_paths(&args, &mut tracker, &args.paths);
+
+    if let Some(path) = &args.files_from {
+        if let Err(e) = on_files_from(&args, &mut tracker, path) {
+            error!(args.counters, "err:{} files_from:{:?}", e, path.display());
+        }
+    }
+
+    let scan = start.elapsed();
+
+    let start = Instant::now();
+    let mut duplicates = detect_duplicates(&args, &tracker);
+    if args.subsets {
+        duplicates.subsets = subset::detect_subsets(&args, &tracker, &duplicates);
+    }
+    if args.directories {
+        tree::detect_duplicate_directories(&args, &tracker, &mut duplicates);
+    }
+    let detect = start.elapsed();
+
+    let start = Instant::now();
+    for group in duplicates.groups.iter_mut() {
+        on_group(&args, group);
+    }
+    let act = start.elapsed();
+
+    if let Some(path) = &args.emit_script {
+        if let Err(e) = script::emit_script(&duplicates, path) {
+            error!(args.counters, "err:{} emit_script:{:?}", e, path.display());
+        }
+        // The script's actions are only planned, so report no clone as
+        // acted on, and no bytes as reclaimed.
+        for group in duplicates.groups.iter_mut() {
+            group.members.iter_mut().for_each(|member| member.action = Action::None);
+        }
+    }
+
+    // Summarize every file acted on, then collapse the file groups under
+    // the duplicate directories, for reporting.
+    let summary = Summary::new(&args.counters, &tracker, &duplicates, scan, detect, act);
+    if args.directories && args.format.has_directories() {
+        tree::collapse(&mut duplicates);
+    }
+
+    if let Some((report, path)) = &args.report {
+        if let Err(e) = report::write_report(&args, *report, &duplicates, path) {
+            error!(args.counters, "err:{} write_report:{:?}", e, path.display());
+        }
+    }
+
+    if let Err(e) = output::write_duplicates(&args, &duplicates, &summary, &mut io::stdout().lock()) {
+        error!(args.counters, "err:{} write_duplicates", e);
+    }
+    if args.by_directory || !args.format.has_summary() {
+        if let Err(e) = summary::write_text(&summary, &mut io::stderr().lock()) {
+            eprintln!("err:{} write_summary", e);
+        }
+    }
+}
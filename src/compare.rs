@@ -0,0 +1,281 @@
+//! N-way comparison within a size bucket.
+//!
+//! Pairwise comparison re-reads the same original for every candidate.
+//! Instead, we open all candidates together, read them in lockstep chunk
+//! by chunk, and split them into equivalence classes as soon as their
+//! chunks diverge. A file that is alone in its class is unique, so we
+//! stop reading it. Each file is read at most once.
+//!
+//! While reading, we also hash each chunk, so each class of duplicates
+//! gets its content digest for free. With `--mmap`, each chunk is a slice
+//! of the file's memory map, rather than a read into a buffer.
+//!
+//! Buckets are compared in parallel, one per worker thread, and together
+//! they open at most `MAX_OPEN_FILES`: a bucket waits until its files fit
+//! under the limit. A bucket of more files than the limit falls back to
+//! digests.
+
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Condvar, Mutex};
+use memmap2::Mmap;
+use crate::digest::{self, Digest};
+use crate::read::{self, ReadOptions};
+
+/// Most files that we open at once, across every worker thread.
+pub const MAX_OPEN_FILES: usize = 256;
+
+/// Count of files open for lockstep comparison, across every worker thread.
+static OPEN_FILES: (Mutex<usize>, Condvar) = (Mutex::new(0), Condvar::new());
+
+/// Most bytes of buffers for one bucket, shared by its files.
+pub const MAX_BUCKET_BUFFER_SIZE: usize = 64 * 1024 * 1024;
+
+/// Least bytes of buffer for one file.
+pub const MIN_BUFFER_SIZE: usize = 64 * 1024;
+
+/// How to compare files that have the same length.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Compare {
+    /// Hash each file fully, scheduled by device, then match digests.
+    Digest,
+    /// Read all files of a size bucket in lockstep, and stop reading a file once it's unique.
+    Lockstep,
+}
+
+impl Default for Compare {
+    fn default() -> Self {
+        Compare::Digest
+    }
+}
+
+impl FromStr for Compare {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "digest" => Ok(Compare::Digest),
+            "lockstep" => Ok(Compare::Lockstep),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("compare:{}", s))),
+        }
+    }
+}
+
+/// One file being read in lockstep, via a buffer, or via a memory map.
+struct Reader {
+    file: Option<File>,
+    map: Option<Mmap>,
+    hasher: blake3::Hasher,
+    buffer: Vec<u8>,
+    offset: usize,
+    n: usize,
+}
+
+impl Reader {
+
+    /// Open a file, and map it if the options say so and it's not empty.
+    fn open<T: AsRef<Path>>(path: T, options: &ReadOptions, buffer_size: usize) -> io::Result<Self> {
+        let file = read::open_sequential(path)?;
+        let map = if options.mmap { read::mmap(&file)? } else { None };
+        let buffer = if map.is_some() { Vec::new() } else { vec![0; buffer_size] };
+        Ok(Reader { file: Some(file), map: map, hasher: blake3::Hasher::new(), buffer: buffer, offset: 0, n: 0 })
+    }
+
+    /// Read and hash the next chunk, of at most `chunk_size` bytes.
+    fn next_chunk(&mut self, chunk_size: usize) -> io::Result<()> {
+        self.n = match &self.map {
+            Some(map) => std::cmp::min(chunk_size, map.len() - self.offset),
+            None => read::read_full(self.file.as_mut().unwrap(), &mut self.buffer)?,
+        };
+        self.offset += self.n;
+        match &self.map {
+            Some(map) => self.hasher.update(&map[self.offset - self.n..self.offset]),
+            None => self.hasher.update(&self.buffer[..self.n]),
+        };
+        Ok(())
+    }
+
+    /// Get the current chunk.
+    fn chunk(&self) -> &[u8] {
+        match &self.map {
+            Some(map) => &map[self.offset - self.n..self.offset],
+            None => &self.buffer[..self.n],
+        }
+    }
+
+    /// Close the file, and free its buffer or map.
+    fn close(&mut self) {
+        self.file = None;
+        self.map = None;
+        self.buffer = Vec::new();
+    }
+
+}
+
+/// A count of open files, held while a bucket is compared, and given back on drop.
+struct OpenFiles(usize);
+
+impl OpenFiles {
+
+    /// Wait until `n` more files fit under `MAX_OPEN_FILES`, then hold them.
+    fn acquire(n: usize) -> Self {
+        let (lock, condvar) = &OPEN_FILES;
+        let mut open = lock.lock().unwrap();
+        while *open + n > MAX_OPEN_FILES {
+            open = condvar.wait(open).unwrap();
+        }
+        *open += n;
+        OpenFiles(n)
+    }
+
+}
+
+impl Drop for OpenFiles {
+    fn drop(&mut self) {
+        let (lock, condvar) = &OPEN_FILES;
+        *lock.lock().unwrap() -= self.0;
+        condvar.notify_all();
+    }
+}
+
+/// Compare files of one size bucket in lockstep, in the same order as the paths.
+///
+/// Return Some(digest) for a file that has at least one duplicate in the bucket,
+/// where duplicates have equal digests, or None for a file that's unique.
+///
+/// A bucket of more files than `MAX_OPEN_FILES` falls back to digests.
+/// Any other bucket waits until its files fit under the limit, with the
+/// files of the buckets that other threads are comparing.
+pub fn lockstep_digests<T: AsRef<Path>>(paths: &[T], options: &ReadOptions) -> Vec<io::Result<Option<Digest>>> {
+    if paths.len() > MAX_OPEN_FILES {
+        return digests_of_duplicates(paths, options);
+    }
+    let _open = OpenFiles::acquire(paths.len());
+    let buffer_size = std::cmp::min(options.buffer_size, std::cmp::max(MIN_BUFFER_SIZE, MAX_BUCKET_BUFFER_SIZE / std::cmp::max(paths.len(), 1)));
+    let mut results: Vec<Option<io::Result<Option<Digest>>>> = paths.iter().map(|_| None).collect();
+    let mut readers: Vec<Reader> = Vec::new();
+    let mut class: Vec<usize> = Vec::new();
+    for (i, path) in paths.iter().enumerate() {
+        match Reader::open(path, options, buffer_size) {
+            Ok(reader) => {
+                readers.push(reader);
+                class.push(i);
+            },
+            Err(e) => {
+                readers.push(Reader { file: None, map: None, hasher: blake3::Hasher::new(), buffer: Vec::new(), offset: 0, n: 0 });
+                results[i] = Some(Err(e));
+            },
+        }
+    }
+    let mut classes: Vec<Vec<usize>> = vec![class];
+    while let Some(class) = classes.pop() {
+        if class.len() < 2 {
+            for i in class { results[i] = Some(Ok(None)); readers[i].close(); }
+            continue
+        }
+        let mut ok: Vec<usize> = Vec::new();
+        for i in class {
+            let reader = &mut readers[i];
+            match reader.next_chunk(buffer_size) {
+                Ok(()) => ok.push(i),
+                Err(e) => { results[i] = Some(Err(e)); reader.close(); },
+            }
+        }
+        let mut splits: Vec<Vec<usize>> = Vec::new();
+        for i in ok {
+            let chunk = readers[i].chunk();
+            match splits.iter_mut().find(|split| readers[split[0]].chunk() == chunk) {
+                Some(split) => split.push(i),
+                None => splits.push(vec![i]),
+            }
+        }
+        for split in splits {
+            if split.len() > 1 && readers[split[0]].n == 0 {
+                let digest = readers[split[0]].hasher.finalize();
+                for i in split { results[i] = Some(Ok(Some(digest))); readers[i].close(); }
+            } else {
+                classes.push(split);
+            }
+        }
+    }
+    results.into_iter().map(|x| x.unwrap_or(Ok(None))).collect()
+}
+
+/// Hash each file fully, then keep the digests that occur more than once.
+fn digests_of_duplicates<T: AsRef<Path>>(paths: &[T], options: &ReadOptions) -> Vec<io::Result<Option<Digest>>> {
+    let digests: Vec<io::Result<Digest>> = paths.iter().map(|path| digest::file_digest(path, options)).collect();
+    let mut counts: HashMap<Digest, usize> = HashMap::new();
+    for digest in digests.iter().flatten() {
+        *counts.entry(*digest).or_insert(0) += 1;
+    }
+    digests
+    .into_iter()
+    .map(|x| x.map(|digest| if counts[&digest] > 1 { Some(digest) } else { None }))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    /// Test `lockstep_digests`, with and without `mmap`, via these files:
+    ///
+    /// * `alpha.txt` which contains `alpha`
+    /// * `alpha_2.txt` which contains `alpha`
+    /// * `bravo.txt` which contains `bravo`
+    /// * `missing.txt` which does not exist
+    ///
+    fn test_lockstep_digests() {
+        let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha.txt"].iter().collect();
+        let b: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha_2.txt"].iter().collect();
+        let c: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "bravo.txt"].iter().collect();
+        let d: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "missing.txt"].iter().collect();
+        for (buffer_size, mmap) in &[(1, false), (4, false), (1024, false), (1, true), (4, true), (1024, true)] {
+            let options = ReadOptions { buffer_size: *buffer_size, mmap: *mmap };
+            let digests = lockstep_digests(&[&a, &c, &b, &d], &options);
+            assert_eq!(digests[0].as_ref().unwrap(), &Some(blake3::hash(b"alpha\n")));
+            assert_eq!(digests[1].as_ref().unwrap(), &None);
+            assert_eq!(digests[2].as_ref().unwrap(), &Some(blake3::hash(b"alpha\n")));
+            assert_eq!(digests[3].as_ref().unwrap_err().kind(), io::ErrorKind::NotFound);
+        }
+    }
+
+    #[test]
+    /// Test `lockstep_digests` with more files than we open at once.
+    /// Must fall back to digests, with the same results.
+    ///
+    fn test_lockstep_digests_x_many_files() {
+        let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha.txt"].iter().collect();
+        let c: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "bravo.txt"].iter().collect();
+        let mut paths: Vec<&PathBuf> = vec![&a; MAX_OPEN_FILES];
+        paths.push(&c);
+        let digests = lockstep_digests(&paths, &ReadOptions::default());
+        assert_eq!(digests[0].as_ref().unwrap(), &Some(blake3::hash(b"alpha\n")));
+        assert_eq!(digests[MAX_OPEN_FILES].as_ref().unwrap(), &None);
+    }
+
+    #[test]
+    /// Test `lockstep_digests` with buckets on many threads, whose files
+    /// together are more than we open at once.
+    /// Must compare each bucket in lockstep, with the same results.
+    ///
+    fn test_lockstep_digests_x_many_threads() {
+        use rayon::prelude::*;
+        let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha.txt"].iter().collect();
+        let c: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "bravo.txt"].iter().collect();
+        let mut paths: Vec<&PathBuf> = vec![&a; MAX_OPEN_FILES / 2];
+        paths.push(&c);
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(8).build().unwrap();
+        let results: Vec<Vec<io::Result<Option<Digest>>>> = pool.install(|| (0..8).into_par_iter().map(|_| lockstep_digests(&paths, &ReadOptions::default())).collect());
+        for digests in results {
+            assert_eq!(digests[0].as_ref().unwrap(), &Some(blake3::hash(b"alpha\n")));
+            assert_eq!(digests[MAX_OPEN_FILES / 2].as_ref().unwrap(), &None);
+        }
+    }
+
+}
@@ -0,0 +1,215 @@
+//! Device-aware I/O scheduling.
+//!
+//! Reading files on different devices in parallel is fast, but reading
+//! many files on one spinning disk in parallel causes seek thrashing.
+//!
+//! We group the hashing work by device id, run each device as its own
+//! job, and limit the reads in flight per device, by default to one on a
+//! rotational disk. On rotational disks, we can also sort the reads by
+//! inode or by physical offset (FIEMAP), so the disk head sweeps in one
+//! direction.
+
+use std::{fs,io};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use rayon::prelude::*;
+use crate::digest::{self, Digest};
+use crate::inode::FileId;
+use crate::read::ReadOptions;
+
+/// How to order the reads on a rotational disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ReadOrder {
+    /// Read in walk order.
+    Walk,
+    /// Read in inode order, which approximates disk layout on many file systems.
+    Inode,
+    /// Read in physical offset order, via FIEMAP, falling back to inode order.
+    Offset,
+}
+
+impl Default for ReadOrder {
+    fn default() -> Self {
+        ReadOrder::Walk
+    }
+}
+
+impl FromStr for ReadOrder {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "walk" => Ok(ReadOrder::Walk),
+            "inode" => Ok(ReadOrder::Inode),
+            "offset" => Ok(ReadOrder::Offset),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("read_order:{}", s))),
+        }
+    }
+}
+
+/// Split a Linux device id into (major, minor).
+pub fn major_minor(dev: u64) -> (u64, u64) {
+    let major = ((dev >> 8) & 0xfff) | ((dev >> 32) & !0xfff);
+    let minor = (dev & 0xff) | ((dev >> 12) & !0xff);
+    (major, minor)
+}
+
+/// Is a device a rotational disk?
+///
+/// We read `/sys/dev/block/MAJOR:MINOR/queue/rotational`, or the parent
+/// disk's queue for a partition. If we can't tell, then we say no.
+pub fn is_rotational(dev: u64) -> bool {
+    let (major, minor) = major_minor(dev);
+    let block = PathBuf::from(format!("/sys/dev/block/{}:{}", major, minor));
+    [block.join("queue").join("rotational"), block.join("..").join("queue").join("rotational")]
+    .iter()
+    .find_map(|path| fs::read_to_string(path).ok())
+    .map(|s| s.trim() == "1")
+    .unwrap_or(false)
+}
+
+/// Get the physical offset of a file's first extent, via the FIEMAP ioctl.
+#[cfg(target_os = "linux")]
+pub fn physical_offset<T: AsRef<Path>>(path: T) -> io::Result<u64> {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    #[repr(C)]
+    struct FiemapExtent {
+        fe_logical: u64,
+        fe_physical: u64,
+        fe_length: u64,
+        fe_reserved64: [u64; 2],
+        fe_flags: u32,
+        fe_reserved: [u32; 3],
+    }
+
+    #[repr(C)]
+    struct Fiemap {
+        fm_start: u64,
+        fm_length: u64,
+        fm_flags: u32,
+        fm_mapped_extents: u32,
+        fm_extent_count: u32,
+        fm_reserved: u32,
+        fm_extents: [FiemapExtent; 1],
+    }
+
+    const FS_IOC_FIEMAP: u64 = 0xC020660B;
+
+    let file = File::open(path)?;
+    let mut fiemap: Fiemap = unsafe { std::mem::zeroed() };
+    fiemap.fm_length = u64::MAX;
+    fiemap.fm_extent_count = 1;
+    let ret = unsafe { libc::ioctl(file.as_raw_fd(), FS_IOC_FIEMAP as _, &mut fiemap as *mut Fiemap) };
+    if ret < 0 { return Err(io::Error::last_os_error()) }
+    match fiemap.fm_mapped_extents {
+        0 => Err(io::Error::other("physical_offset: no extents")),
+        _ => Ok(fiemap.fm_extents[0].fe_physical),
+    }
+}
+
+/// Get the physical offset of a file's first extent; unsupported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn physical_offset<T: AsRef<Path>>(_path: T) -> io::Result<u64> {
+    Err(io::Error::new(io::ErrorKind::Other, "physical_offset: unsupported"))
+}
+
+/// Compute the digests of files, scheduled by device, in the same order as the files.
+///
+/// Each device is a job on the worker pool. Within a device, at most
+/// `jobs_per_device` reads are in flight, or if 0, then 1 on a rotational
+/// disk, and as many as the pool has threads otherwise. Each read is a job
+/// on the worker pool too, so `--jobs` caps the threads overall. On a
+/// rotational disk, the reads are dispatched in `read_order`.
+pub fn file_digests_by_device(files: &[(FileId, PathBuf)], jobs_per_device: usize, read_order: ReadOrder, read_options: &ReadOptions) -> Vec<io::Result<Digest>> {
+    let mut by_dev: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+    for (i, (id, _)) in files.iter().enumerate() {
+        by_dev.entry(id.dev).or_default().push(i);
+    }
+    let results: Vec<Mutex<Option<io::Result<Digest>>>> = files.iter().map(|_| Mutex::new(None)).collect();
+    let by_dev: Vec<(u64, Vec<usize>)> = by_dev.into_iter().collect();
+    by_dev.par_iter().for_each(|(dev, indices)| {
+        let mut indices = indices.clone();
+        let rotational = is_rotational(*dev);
+        if read_order != ReadOrder::Walk && rotational {
+            sort_reads(files, &mut indices, read_order);
+        }
+        let limit = match jobs_per_device {
+            0 => if rotational { 1 } else { rayon::current_num_threads() },
+            n => n,
+        };
+        // Each worker takes the next index in read order, so the reads are
+        // dispatched in order, with at most `limit` in flight.
+        let next = AtomicUsize::new(0);
+        (0..std::cmp::min(limit, indices.len())).into_par_iter().for_each(|_| loop {
+            let k = next.fetch_add(1, Ordering::SeqCst);
+            if k >= indices.len() { break }
+            let i = indices[k];
+            *results[i].lock().unwrap() = Some(digest::file_digest(&files[i].1, read_options));
+        });
+    });
+    results.into_iter().map(|x| x.into_inner().unwrap().unwrap()).collect()
+}
+
+/// Sort the indices of files on one device by read order.
+fn sort_reads(files: &[(FileId, PathBuf)], indices: &mut Vec<usize>, read_order: ReadOrder) {
+    match read_order {
+        ReadOrder::Walk => (),
+        ReadOrder::Inode => indices.sort_by_key(|i| files[*i].0.ino),
+        ReadOrder::Offset => {
+            let offsets: BTreeMap<usize, u64> = indices
+            .par_iter()
+            .map(|i| (*i, physical_offset(&files[*i].1).unwrap_or(u64::MAX)))
+            .collect();
+            indices.sort_by_key(|i| (offsets[i], files[*i].0.ino));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test `major_minor` with typical device ids.
+    ///
+    fn test_major_minor() {
+        assert_eq!(major_minor(0x0801), (8, 1));
+        assert_eq!(major_minor(0x10300), (259, 0));
+    }
+
+    #[test]
+    /// Test `ReadOrder::from_str` with valid and invalid input.
+    ///
+    fn test_read_order_from_str() {
+        assert_eq!("walk".parse::<ReadOrder>().unwrap(), ReadOrder::Walk);
+        assert_eq!("inode".parse::<ReadOrder>().unwrap(), ReadOrder::Inode);
+        assert_eq!("offset".parse::<ReadOrder>().unwrap(), ReadOrder::Offset);
+        assert_eq!("other".parse::<ReadOrder>().unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    /// Test `file_digests_by_device` with and without a per-device limit,
+    /// on a pool of one thread, as with `--jobs 1`.
+    /// Must be in the same order as the files.
+    ///
+    fn test_file_digests_by_device() {
+        let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha.txt"].iter().collect();
+        let c: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "bravo.txt"].iter().collect();
+        let files = vec![
+            (FileId::from_path(&a).unwrap(), a.clone()),
+            (FileId::from_path(&c).unwrap(), c.clone()),
+        ];
+        let pool = rayon::ThreadPoolBuilder::new().num_threads(1).build().unwrap();
+        for jobs_per_device in 0..3 {
+            let digests = pool.install(|| file_digests_by_device(&files, jobs_per_device, ReadOrder::Offset, &ReadOptions::default()));
+            assert_eq!(digests[0].as_ref().unwrap(), &blake3::hash(b"alpha\n"));
+            assert_eq!(digests[1].as_ref().unwrap(), &blake3::hash(b"bravo\n"));
+        }
+    }
+
+}
@@ -0,0 +1,222 @@
+//! Differences between two directories, by content.
+//!
+//! We compare files by content digest rather than by name, so a renamed
+//! or moved file is identical, with a mapping from its path in A to its
+//! path in B. A file whose content is in only one directory is only in
+//! that directory, unless the other directory has a file at the same
+//! path, in which case the path is changed.
+//!
+//! We hash only files whose length occurs in the other directory, or
+//! whose path occurs in the other directory, because any other file
+//! can't match.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use rayon::prelude::*;
+use serde_json::json;
+use crate::FileLen;
+use crate::args::Args;
+use crate::digest::{self, Digest};
+use crate::output::Format;
+
+/// One file of a directory, by its path relative to the directory.
+#[derive(Clone, Debug)]
+pub struct DiffFile {
+    pub path: PathBuf,
+    pub len: FileLen,
+    pub digest: Option<Digest>,
+}
+
+/// Differences between two directories, with paths relative to each.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Diff {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    pub only_in_a: Vec<PathBuf>,
+    pub only_in_b: Vec<PathBuf>,
+    /// Each file of A whose content is in B, with the path in B, which is the same path if possible.
+    pub identical: Vec<(PathBuf, PathBuf)>,
+    /// Each path in both whose content differs and whose content is in only one.
+    pub changed: Vec<PathBuf>,
+}
+
+/// List the files of a directory, in path order, by walking with the args.
+pub fn files<T: AsRef<Path>>(args: &Args, dir: T) -> Vec<DiffFile> {
+    let dir = dir.as_ref();
+    let mut files: Vec<DiffFile> = crate::walk::walker(args, dir)
+    .filter(|e| e.file_type().is_file())
+    .filter_map(|e| {
+        let len = match e.metadata() {
+            Ok(metadata) => metadata.len(),
+            Err(err) => { error!(args.counters, "err:{} metadata:{:?}", err, e.path()); return None },
+        };
+        let path = e.path();
+        path.strip_prefix(dir).ok().map(|path| DiffFile { path: path.to_path_buf(), len: len, digest: None })
+    })
+    .collect();
+    files.sort_by(|x, y| x.path.cmp(&y.path));
+    files
+}
+
+/// Hash the files that could match a file of the other directory, on the worker pool.
+fn hash_candidates(args: &Args, dir: &Path, files: &mut [DiffFile], other: &[DiffFile]) {
+    let lens: HashSet<FileLen> = other.iter().map(|file| file.len).collect();
+    let paths: HashSet<&Path> = other.iter().map(|file| file.path.as_path()).collect();
+    files
+    .par_iter_mut()
+    .filter(|file| lens.contains(&file.len) || paths.contains(file.path.as_path()))
+    .for_each(|file| match digest::file_digest(dir.join(&file.path), &args.read_options) {
+        Ok(digest) => file.digest = Some(digest),
+        Err(e) => error!(args.counters, "err:{} file_digest:{:?}", e, dir.join(&file.path)),
+    });
+}
+
+/// Compare two directories by content, and hash their files as needed.
+///
+/// Return the diff, and the files of each directory, with digests of
+/// the files that could match.
+pub fn diff_files<T: AsRef<Path>>(args: &Args, a: T, b: T) -> (Diff, Vec<DiffFile>, Vec<DiffFile>) {
+    let (a, b) = (a.as_ref(), b.as_ref());
+    let mut files_a = files(args, a);
+    let mut files_b = files(args, b);
+    hash_candidates(args, a, &mut files_a, &files_b);
+    hash_candidates(args, b, &mut files_b, &files_a);
+    let diff = diff_of(a, b, &files_a, &files_b);
+    (diff, files_a, files_b)
+}
+
+/// Compare two directories by content.
+pub fn diff<T: AsRef<Path>>(args: &Args, a: T, b: T) -> Diff {
+    diff_files(args, a, b).0
+}
+
+/// Compare the files of two directories, which have digests as needed.
+pub fn diff_of(a: &Path, b: &Path, files_a: &[DiffFile], files_b: &[DiffFile]) -> Diff {
+    let mut contents_b: HashMap<Digest, Vec<&Path>> = HashMap::new();
+    for file in files_b.iter() {
+        if let Some(digest) = file.digest {
+            contents_b.entry(digest).or_default().push(&file.path);
+        }
+    }
+    let contents_a: HashSet<Digest> = files_a.iter().filter_map(|file| file.digest).collect();
+    let paths_a: HashSet<&Path> = files_a.iter().map(|file| file.path.as_path()).collect();
+    let paths_b: HashSet<&Path> = files_b.iter().map(|file| file.path.as_path()).collect();
+    let mut diff = Diff { a: a.to_path_buf(), b: b.to_path_buf(), ..Diff::default() };
+    let mut changed: HashSet<&Path> = HashSet::new();
+    for file in files_a.iter() {
+        match file.digest.and_then(|digest| contents_b.get(&digest)) {
+            Some(paths) => {
+                let path_b = paths.iter().find(|path| **path == file.path).unwrap_or(&paths[0]);
+                diff.identical.push((file.path.clone(), path_b.to_path_buf()));
+            },
+            None if paths_b.contains(file.path.as_path()) => { changed.insert(&file.path); },
+            None => diff.only_in_a.push(file.path.clone()),
+        }
+    }
+    for file in files_b.iter() {
+        if file.digest.map(|digest| contents_a.contains(&digest)).unwrap_or(false) { continue }
+        if paths_a.contains(file.path.as_path()) {
+            changed.insert(&file.path);
+        } else {
+            diff.only_in_b.push(file.path.clone());
+        }
+    }
+    diff.changed = changed.into_iter().map(|path| path.to_path_buf()).collect();
+    diff.changed.sort();
+    diff
+}
+
+/// Write the diff, as one JSON object, or as NDJSON with one object per
+/// line and a `type` field, or else as text.
+///
+/// Text lines are `Only in A:`, `Only in B:`, `Identical:` with both paths,
+/// and `Changed:` with both paths.
+pub fn write_diff<W: Write>(args: &Args, diff: &Diff, w: &mut W) -> io::Result<()> {
+    let (a, b) = (&diff.a, &diff.b);
+    let lossy = |dir: &Path, path: &Path| dir.join(path).to_string_lossy().into_owned();
+    match args.format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *w, &json!({
+                "a": a.to_string_lossy(),
+                "b": b.to_string_lossy(),
+                "only_in_a": diff.only_in_a.iter().map(|path| lossy(a, path)).collect::<Vec<String>>(),
+                "only_in_b": diff.only_in_b.iter().map(|path| lossy(b, path)).collect::<Vec<String>>(),
+                "identical": diff.identical.iter().map(|(x, y)| json!({ "a": lossy(a, x), "b": lossy(b, y) })).collect::<Vec<serde_json::Value>>(),
+                "changed": diff.changed.iter().map(|path| json!({ "a": lossy(a, path), "b": lossy(b, path) })).collect::<Vec<serde_json::Value>>(),
+            }))?;
+            writeln!(w)
+        },
+        Format::Ndjson => {
+            let lines = diff.only_in_a.iter().map(|path| json!({ "type": "only_in_a", "path": lossy(a, path) }))
+            .chain(diff.only_in_b.iter().map(|path| json!({ "type": "only_in_b", "path": lossy(b, path) })))
+            .chain(diff.identical.iter().map(|(x, y)| json!({ "type": "identical", "a": lossy(a, x), "b": lossy(b, y) })))
+            .chain(diff.changed.iter().map(|path| json!({ "type": "changed", "a": lossy(a, path), "b": lossy(b, path) })));
+            for line in lines {
+                serde_json::to_writer(&mut *w, &line)?;
+                writeln!(w)?;
+            }
+            Ok(())
+        },
+        _ => {
+            for path in diff.only_in_a.iter() { writeln!(w, "Only in A: {:?}", a.join(path))?; }
+            for path in diff.only_in_b.iter() { writeln!(w, "Only in B: {:?}", b.join(path))?; }
+            for (x, y) in diff.identical.iter() { writeln!(w, "Identical: {:?} {:?}", a.join(x), b.join(y))?; }
+            for path in diff.changed.iter() { writeln!(w, "Changed: {:?} {:?}", a.join(path), b.join(path))?; }
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test `diff` via these files:
+    ///
+    /// * `a/alpha.txt` which contains `alpha`
+    /// * `a/bravo.txt` which contains `bravo`
+    /// * `a/charlie.txt` which contains `charlie`
+    /// * `a/delta.txt` which contains `delta`
+    /// * `b/alpha.txt` which contains `alpha`
+    /// * `b/renamed/bravo_2.txt` which contains `bravo`
+    /// * `b/charlie.txt` which contains `CHARLIE`
+    /// * `b/echo.txt` which contains `echo`
+    ///
+    fn test_diff() {
+        let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "diff", "a"].iter().collect();
+        let b: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "diff", "b"].iter().collect();
+        let diff = diff(&Args::default(), &a, &b);
+        assert_eq!(diff.only_in_a, vec![PathBuf::from("delta.txt")]);
+        assert_eq!(diff.only_in_b, vec![PathBuf::from("echo.txt")]);
+        assert_eq!(diff.identical, vec![
+            (PathBuf::from("alpha.txt"), PathBuf::from("alpha.txt")),
+            (PathBuf::from("bravo.txt"), PathBuf::from("renamed/bravo_2.txt")),
+        ]);
+        assert_eq!(diff.changed, vec![PathBuf::from("charlie.txt")]);
+    }
+
+    #[test]
+    /// Test `write_diff` with `Format::Ndjson`.
+    /// Must write one compact object per line, each with its type.
+    ///
+    fn test_write_diff_x_ndjson() {
+        let diff = Diff {
+            a: PathBuf::from("a"),
+            b: PathBuf::from("b"),
+            only_in_a: vec![PathBuf::from("delta.txt")],
+            identical: vec![(PathBuf::from("bravo.txt"), PathBuf::from("renamed/bravo_2.txt"))],
+            ..Diff::default()
+        };
+        let mut w: Vec<u8> = Vec::new();
+        write_diff(&Args { format: Format::Ndjson, ..Args::default() }, &diff, &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        let values: Vec<serde_json::Value> = s.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(values, vec![
+            json!({ "type": "only_in_a", "path": "a/delta.txt" }),
+            json!({ "type": "identical", "a": "a/bravo.txt", "b": "b/renamed/bravo_2.txt" }),
+        ]);
+    }
+
+}
@@ -0,0 +1,69 @@
+//! Content digests.
+//!
+//! We use BLAKE3, which is fast, parallel-friendly, and cryptographic,
+//! so two files with equal digests have equal content.
+
+use std::io;
+use std::path::Path;
+use crate::read::{self, ReadOptions};
+
+/// Content digest
+pub type Digest = blake3::Hash;
+
+/// Compute the digest of a file's content, read with these options.
+pub fn file_digest<T: AsRef<Path>>(path: T, options: &ReadOptions) -> io::Result<Digest> {
+    let mut file = read::open_sequential(path)?;
+    let mut hasher = blake3::Hasher::new();
+    if options.mmap {
+        if let Some(mmap) = read::mmap(&file)? {
+            hasher.update(&mmap[..]);
+        }
+        return Ok(hasher.finalize())
+    }
+    let len = file.metadata()?.len();
+    let mut buffer = vec![0; std::cmp::min(options.buffer_size as u64, std::cmp::max(len, 1)) as usize];
+    loop {
+        let n = read::read_full(&mut file, &mut buffer)?;
+        if n == 0 { return Ok(hasher.finalize()) }
+        hasher.update(&buffer[..n]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    /// Test `file_digest` with one file.
+    /// Must be the digest of the content.
+    ///
+    fn test_file_digest() {
+        let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha.txt"].iter().collect();
+        assert_eq!(file_digest(&a, &ReadOptions::default()).unwrap(), blake3::hash(b"alpha\n"));
+        assert_eq!(file_digest(&a, &ReadOptions { buffer_size: 4, mmap: false }).unwrap(), blake3::hash(b"alpha\n"));
+        assert_eq!(file_digest(&a, &ReadOptions { buffer_size: 4, mmap: true }).unwrap(), blake3::hash(b"alpha\n"));
+    }
+
+    #[test]
+    /// Test `file_digest` with equal files and inequal files.
+    ///
+    fn test_file_digest_x_equal_and_inequal() {
+        let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha.txt"].iter().collect();
+        let b: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "alpha_2.txt"].iter().collect();
+        let c: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "bravo.txt"].iter().collect();
+        let options = ReadOptions::default();
+        assert_eq!(file_digest(&a, &options).unwrap(), file_digest(&b, &options).unwrap());
+        assert_ne!(file_digest(&a, &options).unwrap(), file_digest(&c, &options).unwrap());
+    }
+
+    #[test]
+    /// Test `file_digest` with a missing file.
+    /// Must err.
+    ///
+    fn test_file_digest_x_missing() {
+        let a: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "file_bytes_eq", "missing.txt"].iter().collect();
+        assert_eq!(file_digest(a, &ReadOptions::default()).unwrap_err().kind(), io::ErrorKind::NotFound);
+    }
+
+}
@@ -0,0 +1,206 @@
+//! Duplication per directory.
+//!
+//! We total the bytes of clones per directory, either in the clone's own
+//! directory, or rolled up the tree like `du`, so each directory counts
+//! the clones anywhere below it, up to the scanned roots. This shows which directories contribute
+//! the most redundancy.
+//!
+//! We also total the bytes shared per pair of directories, where a group
+//! shares its size between each pair of directories that hold its members.
+
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use serde_json::json;
+use crate::FileLen;
+use crate::args::Args;
+use crate::group::{Duplicates, serialize_path};
+use crate::{inode, tree};
+
+/// Totals of clones in one directory.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct DirectoryTotal {
+    pub clones: usize,
+    pub bytes_reclaimable: FileLen,
+}
+
+/// Bytes shared by two directories.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DirectoryPair {
+    #[serde(serialize_with = "serialize_path")]
+    pub a: PathBuf,
+    #[serde(serialize_with = "serialize_path")]
+    pub b: PathBuf,
+    pub bytes_shared: FileLen,
+}
+
+/// Total the clones per directory, sorted by bytes reclaimable, most first.
+///
+/// With rollup, each clone counts in each ancestor directory too.
+/// A clone that shares its inode with the original or with an earlier
+/// clone reclaims no bytes, yet still counts as a clone.
+pub fn totals(args: &Args, duplicates: &Duplicates, rollup: bool) -> Vec<(PathBuf, DirectoryTotal)> {
+    let mut totals: HashMap<PathBuf, DirectoryTotal> = HashMap::new();
+    for group in duplicates.groups.iter() {
+        let clone_bytes = inode::clone_bytes(group.clones().map(|clone| (clone.id(), clone.size)), &group.original_ids());
+        for (clone, bytes) in group.clones().zip(clone_bytes) {
+            let dirs: Vec<&Path> = match rollup {
+                true => clone.path.ancestors().skip(1).filter(|dir| tree::is_within_paths(args, dir)).collect(),
+                false => clone.path.parent().into_iter().collect(),
+            };
+            for dir in dirs {
+                let total = totals.entry(dir.to_path_buf()).or_default();
+                total.clones += 1;
+                total.bytes_reclaimable += bytes;
+            }
+        }
+    }
+    let mut totals: Vec<(PathBuf, DirectoryTotal)> = totals.into_iter().collect();
+    totals.sort_by(|a, b| b.1.bytes_reclaimable.cmp(&a.1.bytes_reclaimable).then_with(|| a.0.cmp(&b.0)));
+    totals
+}
+
+/// Total the bytes shared per pair of directories, sorted by bytes shared, most first.
+pub fn pairs(duplicates: &Duplicates) -> Vec<DirectoryPair> {
+    let mut shared: HashMap<(&Path, &Path), FileLen> = HashMap::new();
+    for group in duplicates.groups.iter() {
+        let mut dirs: Vec<&Path> = group.members.iter().filter_map(|member| member.path.parent()).collect();
+        dirs.sort();
+        dirs.dedup();
+        for (i, a) in dirs.iter().enumerate() {
+            for b in dirs.iter().skip(i + 1) {
+                *shared.entry((a, b)).or_insert(0) += group.size;
+            }
+        }
+    }
+    let mut pairs: Vec<DirectoryPair> = shared
+    .into_iter()
+    .map(|((a, b), bytes_shared)| DirectoryPair { a: a.to_path_buf(), b: b.to_path_buf(), bytes_shared: bytes_shared })
+    .collect();
+    pairs.sort_by(|x, y| y.bytes_shared.cmp(&x.bytes_shared).then_with(|| (&x.a, &x.b).cmp(&(&y.a, &y.b))));
+    pairs
+}
+
+/// Write the totals rolled up per directory, then the pairs, as text.
+///
+/// Each line is tab-separated, like `du`: bytes, clones, and directory,
+/// or bytes shared, directory, and directory.
+pub fn write_text<W: Write>(args: &Args, duplicates: &Duplicates, w: &mut W) -> io::Result<()> {
+    writeln!(w, "Directories:")?;
+    for (dir, total) in totals(args, duplicates, true) {
+        writeln!(w, "{}\t{}\t{}", total.bytes_reclaimable, total.clones, dir.display())?;
+    }
+    writeln!(w, "Directory pairs:")?;
+    for pair in pairs(duplicates) {
+        writeln!(w, "{}\t{}\t{}", pair.bytes_shared, pair.a.display(), pair.b.display())?;
+    }
+    Ok(())
+}
+
+/// Write the totals rolled up per directory, then the pairs, as one JSON document.
+pub fn write_json<W: Write>(args: &Args, duplicates: &Duplicates, w: &mut W) -> io::Result<()> {
+    let directories: Vec<serde_json::Value> = totals(args, duplicates, true)
+    .into_iter()
+    .map(|(dir, total)| json!({ "path": dir.to_string_lossy(), "clones": total.clones, "bytes_reclaimable": total.bytes_reclaimable }))
+    .collect();
+    serde_json::to_writer_pretty(&mut *w, &json!({ "directories": directories, "pairs": pairs(duplicates) }))?;
+    writeln!(w)
+}
+
+/// Write the totals rolled up per directory, then the pairs, as NDJSON,
+/// with one object per line and a `type` field.
+pub fn write_ndjson<W: Write>(args: &Args, duplicates: &Duplicates, w: &mut W) -> io::Result<()> {
+    for (dir, total) in totals(args, duplicates, true) {
+        serde_json::to_writer(&mut *w, &json!({ "type": "directory", "path": dir.to_string_lossy(), "clones": total.clones, "bytes_reclaimable": total.bytes_reclaimable }))?;
+        writeln!(w)?;
+    }
+    for pair in pairs(duplicates) {
+        serde_json::to_writer(&mut *w, &json!({ "type": "pair", "pair": pair }))?;
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::{Role, duplicates};
+
+    #[test]
+    /// Test `totals` without rollup, with a clone that is a hardlink to its original.
+    /// Must count the clone, yet not its bytes.
+    ///
+    fn test_totals() {
+        assert_eq!(totals(&Args::default(), &duplicates(), false), vec![
+            (PathBuf::from("c"), DirectoryTotal { clones: 1, bytes_reclaimable: 8 }),
+            (PathBuf::from("b"), DirectoryTotal { clones: 2, bytes_reclaimable: 6 }),
+        ]);
+    }
+
+    #[test]
+    /// Test `totals` with rollup, with each clone moved under `x`.
+    /// Must count each clone in each ancestor directory too.
+    ///
+    fn test_totals_x_rollup() {
+        let mut duplicates = duplicates();
+        for group in duplicates.groups.iter_mut() {
+            for member in group.members.iter_mut().filter(|member| member.role == Role::Clone) {
+                member.path = Path::new("x").join(&member.path);
+            }
+        }
+        assert_eq!(totals(&Args::default(), &duplicates, true), vec![
+            (PathBuf::from("x"), DirectoryTotal { clones: 3, bytes_reclaimable: 14 }),
+            (PathBuf::from("x/c"), DirectoryTotal { clones: 1, bytes_reclaimable: 8 }),
+            (PathBuf::from("x/b"), DirectoryTotal { clones: 2, bytes_reclaimable: 6 }),
+        ]);
+    }
+
+    #[test]
+    /// Test `totals` with rollup, with each member moved under `/x/y`, and `/x/y` as the root.
+    /// Must not count the directories above the root.
+    ///
+    fn test_totals_x_rollup_x_root() {
+        let mut duplicates = duplicates();
+        for group in duplicates.groups.iter_mut() {
+            for member in group.members.iter_mut() {
+                member.path = Path::new("/x/y").join(&member.path);
+            }
+        }
+        let args = Args { paths: vec![PathBuf::from("/x/y")], ..Args::default() };
+        assert_eq!(totals(&args, &duplicates, true), vec![
+            (PathBuf::from("/x/y"), DirectoryTotal { clones: 3, bytes_reclaimable: 14 }),
+            (PathBuf::from("/x/y/c"), DirectoryTotal { clones: 1, bytes_reclaimable: 8 }),
+            (PathBuf::from("/x/y/b"), DirectoryTotal { clones: 2, bytes_reclaimable: 6 }),
+        ]);
+    }
+
+    #[test]
+    /// Test `pairs`.
+    /// Must total the group size per pair of distinct directories.
+    ///
+    fn test_pairs() {
+        assert_eq!(pairs(&duplicates()), vec![
+            DirectoryPair { a: PathBuf::from("a"), b: PathBuf::from("c"), bytes_shared: 8 },
+            DirectoryPair { a: PathBuf::from("a"), b: PathBuf::from("b"), bytes_shared: 6 },
+        ]);
+    }
+
+    #[test]
+    /// Test `write_ndjson`.
+    /// Must write one compact object per directory, then per pair.
+    ///
+    fn test_write_ndjson() {
+        let mut w: Vec<u8> = Vec::new();
+        write_ndjson(&Args::default(), &duplicates(), &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        let values: Vec<serde_json::Value> = s.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(values, vec![
+            json!({ "type": "directory", "path": "c", "clones": 1, "bytes_reclaimable": 8 }),
+            json!({ "type": "directory", "path": "b", "clones": 2, "bytes_reclaimable": 6 }),
+            json!({ "type": "pair", "pair": { "a": "a", "b": "c", "bytes_shared": 8 } }),
+            json!({ "type": "pair", "pair": { "a": "a", "b": "b", "bytes_shared": 6 } }),
+        ]);
+    }
+
+}
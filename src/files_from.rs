@@ -0,0 +1,80 @@
+//! Read the list of files to consider from stdin or a file.
+//!
+//! The list can be newline-separated, such as from `find` or `fd`,
+//! or NUL-separated, such as from `find -print0` or `fd -0`.
+//! If the list contains any NUL byte, then we split on NUL only,
+//! so paths may contain newlines.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::ffi::OsStr;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+/// Open a list of files, where `-` means stdin.
+pub fn open<T: AsRef<Path>>(path: T) -> io::Result<Box<dyn Read>> {
+    match path.as_ref() == Path::new("-") {
+        true => Ok(Box::new(io::stdin())),
+        false => Ok(Box::new(File::open(path)?)),
+    }
+}
+
+/// Read a list of files, separated by NUL or by newline.
+///
+/// Empty entries are skipped, and so is a trailing carriage return.
+pub fn read_paths<R: Read>(mut reader: R) -> io::Result<Vec<PathBuf>> {
+    let mut bytes: Vec<u8> = Vec::new();
+    reader.read_to_end(&mut bytes)?;
+    Ok(split_paths(&bytes))
+}
+
+/// Split bytes into paths, separated by NUL or by newline.
+pub fn split_paths(bytes: &[u8]) -> Vec<PathBuf> {
+    let separator = if bytes.contains(&b'\0') { b'\0' } else { b'\n' };
+    bytes
+    .split(|b| *b == separator)
+    .map(|x| if separator == b'\n' && x.ends_with(b"\r") { &x[..x.len() - 1] } else { x })
+    .filter(|x| !x.is_empty())
+    .map(|x| PathBuf::from(OsStr::from_bytes(x)))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test `read_paths` with newline-separated paths.
+    ///
+    fn test_read_paths_x_newline() {
+        let bytes: &[u8] = b"alpha.txt\nbravo.txt\r\n\ncharlie.txt";
+        assert_eq!(
+            read_paths(bytes).unwrap(),
+            vec![PathBuf::from("alpha.txt"), PathBuf::from("bravo.txt"), PathBuf::from("charlie.txt")]
+        );
+    }
+
+    #[test]
+    /// Test `read_paths` with NUL-separated paths, which may contain newlines.
+    ///
+    fn test_read_paths_x_nul() {
+        let bytes: &[u8] = b"alpha.txt\0bravo\n.txt\0";
+        assert_eq!(
+            read_paths(bytes).unwrap(),
+            vec![PathBuf::from("alpha.txt"), PathBuf::from("bravo\n.txt")]
+        );
+    }
+
+    #[test]
+    /// Test `read_paths` with a non-UTF-8 path.
+    /// Must keep the bytes.
+    ///
+    fn test_read_paths_x_non_utf8() {
+        let bytes: &[u8] = b"\xff.txt\0";
+        assert_eq!(
+            read_paths(bytes).unwrap(),
+            vec![PathBuf::from(OsStr::from_bytes(b"\xff.txt"))]
+        );
+    }
+
+}
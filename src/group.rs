@@ -0,0 +1,219 @@
+//! Duplicate groups.
+//!
+//! A duplicate group is a set of files with equal content. One file is
+//! the original, which we keep, and the others are clones, which we can
+//! act on. A file that is in no group is unique.
+//!
+//! Each member is one path, so a hardlink set shows as members that
+//! share one (dev, inode).
+//!
+//! A duplicate directory group is a set of directories with equal trees.
+
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Serializer};
+use crate::FileLen;
+use crate::action::Action;
+use crate::digest::Digest;
+use crate::inode::{FileId, LogicalFile};
+use crate::subset::Subset;
+
+/// The role of a file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Original,
+    Clone,
+    Unique,
+}
+
+/// One path of a duplicate group, or one unique path.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Member {
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    pub size: FileLen,
+    pub dev: u64,
+    pub inode: u64,
+    #[serde(serialize_with = "serialize_mtime")]
+    pub mtime: Option<SystemTime>,
+    pub role: Role,
+    pub action: Action,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Member {
+
+    /// Create a member for each path of a logical file.
+    pub fn from_logical_file(file: &LogicalFile, role: Role) -> Vec<Member> {
+        file.paths.iter().map(|path| Member {
+            path: path.clone(),
+            size: file.len,
+            dev: file.id.dev,
+            inode: file.id.ino,
+            mtime: fs::metadata(path).and_then(|m| m.modified()).ok(),
+            role: role,
+            action: Action::None,
+            error: None,
+        }).collect()
+    }
+
+    /// Get the file id i.e. the device id and the inode number.
+    pub fn id(&self) -> FileId {
+        FileId { dev: self.dev, ino: self.inode }
+    }
+
+}
+
+/// A set of files with equal content.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DuplicateGroup {
+    #[serde(serialize_with = "serialize_digest")]
+    pub digest: Digest,
+    pub size: FileLen,
+    pub bytes_reclaimable: FileLen,
+    pub members: Vec<Member>,
+}
+
+impl DuplicateGroup {
+
+    /// Get the path of the original.
+    pub fn original(&self) -> Option<&PathBuf> {
+        self.members.iter().find(|member| member.role == Role::Original).map(|member| &member.path)
+    }
+
+    /// Get the clones.
+    pub fn clones(&self) -> impl Iterator<Item = &Member> {
+        self.members.iter().filter(|member| member.role == Role::Clone)
+    }
+
+    /// Get the file ids of the originals.
+    pub fn original_ids(&self) -> HashSet<FileId> {
+        self.members.iter().filter(|member| member.role == Role::Original).map(|member| member.id()).collect()
+    }
+
+    /// Apply an action to each clone, and record the action and any error,
+    /// for the caller to report.
+    pub fn apply(&mut self, action: Action) {
+        let original: PathBuf = match self.original() {
+            Some(path) => path.clone(),
+            None => return,
+        };
+        if action == Action::None { return }
+        for clone in self.members.iter_mut().filter(|member| member.role == Role::Clone) {
+            clone.action = action;
+            if let Err(e) = action.apply(original.as_path(), clone.path.as_path()) {
+                clone.error = Some(e.to_string());
+            }
+        }
+    }
+
+}
+
+/// One directory of a duplicate directory group.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DirectoryMember {
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    pub role: Role,
+}
+
+/// A set of directories with equal trees.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DirectoryGroup {
+    #[serde(serialize_with = "serialize_digest")]
+    pub digest: Digest,
+    /// Total bytes of the files of one directory.
+    pub size: FileLen,
+    /// Count of the files of one directory.
+    pub files: usize,
+    pub bytes_reclaimable: FileLen,
+    pub members: Vec<DirectoryMember>,
+}
+
+impl DirectoryGroup {
+
+    /// Get the path of the original.
+    pub fn original(&self) -> Option<&PathBuf> {
+        self.members.iter().find(|member| member.role == Role::Original).map(|member| &member.path)
+    }
+
+}
+
+/// The results of detecting duplicates.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Duplicates {
+    pub groups: Vec<DuplicateGroup>,
+    pub uniques: Vec<Member>,
+    pub directories: Vec<DirectoryGroup>,
+    pub subsets: Vec<Subset>,
+}
+
+pub(crate) fn serialize_path<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&path.to_string_lossy())
+}
+
+fn serialize_digest<S: Serializer>(digest: &Digest, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&digest.to_hex())
+}
+
+fn serialize_mtime<S: Serializer>(mtime: &Option<SystemTime>, serializer: S) -> Result<S::Ok, S::Error> {
+    match mtime.and_then(|t| t.duration_since(UNIX_EPOCH).ok()) {
+        Some(duration) => serializer.serialize_u64(duration.as_secs()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Create a member for tests, on device 1, with an mtime and no action.
+#[cfg(test)]
+pub(crate) fn member(path: &str, size: FileLen, inode: u64, role: Role) -> Member {
+    Member {
+        path: PathBuf::from(path),
+        size: size,
+        dev: 1,
+        inode: inode,
+        mtime: Some(UNIX_EPOCH + std::time::Duration::from_secs(1000)),
+        role: role,
+        action: Action::None,
+        error: None,
+    }
+}
+
+/// Create duplicates for tests, with two groups and one unique:
+///
+/// * `a/alpha.txt`, `b/alpha.txt`, and `b/alpha_2.txt`, which is a hardlink to `a/alpha.txt`
+/// * `a/bravo.txt` and `c/bravo.txt`
+/// * `a/charlie.txt`, which is unique
+///
+#[cfg(test)]
+pub(crate) fn duplicates() -> Duplicates {
+    Duplicates {
+        groups: vec![
+            DuplicateGroup {
+                digest: blake3::hash(b"alpha\n"),
+                size: 6,
+                bytes_reclaimable: 6,
+                members: vec![
+                    member("a/alpha.txt", 6, 10, Role::Original),
+                    member("b/alpha.txt", 6, 20, Role::Clone),
+                    member("b/alpha_2.txt", 6, 10, Role::Clone),
+                ],
+            },
+            DuplicateGroup {
+                digest: blake3::hash(b"bravo\n"),
+                size: 8,
+                bytes_reclaimable: 8,
+                members: vec![
+                    member("a/bravo.txt", 8, 30, Role::Original),
+                    member("c/bravo.txt", 8, 40, Role::Clone),
+                ],
+            },
+        ],
+        uniques: vec![member("a/charlie.txt", 8, 50, Role::Unique)],
+        directories: vec![],
+        subsets: vec![],
+    }
+}
@@ -0,0 +1,160 @@
+//! File identity by device and inode, for hardlink awareness.
+//!
+//! Hardlinks are paths that share one inode, so they share one copy
+//! of the content. We group paths by (dev, inode) into logical files,
+//! so a hardlink set counts as one file and its bytes count once.
+
+use std::{fs,io};
+use std::collections::{BTreeMap, HashSet};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use crate::FileLen;
+use crate::summary::Counters;
+
+/// File identity i.e. the device id and the inode number.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FileId {
+    pub dev: u64,
+    pub ino: u64,
+}
+
+impl FileId {
+
+    /// Get the file id from metadata.
+    pub fn from_metadata(metadata: &fs::Metadata) -> Self {
+        FileId { dev: metadata.dev(), ino: metadata.ino() }
+    }
+
+    /// Get the file id from a path, not following symlinks, so a link
+    /// never shares the id of its target.
+    pub fn from_path<T: AsRef<Path>>(path: T) -> io::Result<Self> {
+        Ok(FileId::from_metadata(&fs::symlink_metadata(path)?))
+    }
+
+}
+
+/// A logical file is one inode with one or more hardlink paths.
+///
+/// The paths are sorted, and the first path is the representative.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogicalFile {
+    pub id: FileId,
+    pub len: FileLen,
+    pub paths: Vec<PathBuf>,
+}
+
+impl LogicalFile {
+
+    /// Get the representative path.
+    pub fn path(&self) -> &PathBuf {
+        &self.paths[0]
+    }
+
+    /// Is this logical file a hardlink set i.e. more than one path?
+    pub fn is_hardlinked(&self) -> bool {
+        self.paths.len() > 1
+    }
+
+}
+
+/// Group paths by file id into logical files, sorted by representative path.
+///
+/// A path that can't be read is reported, and counted, then skipped. A symlink is skipped,
+/// because it's not a hardlink of its target, and the tracker resolves links.
+pub fn logical_files<I, P>(counters: &Counters, paths: I) -> Vec<LogicalFile>
+where
+    I: IntoIterator<Item = P>,
+    P: AsRef<Path>,
+{
+    let mut map: BTreeMap<FileId, LogicalFile> = BTreeMap::new();
+    for path in paths {
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(metadata) => if metadata.file_type().is_symlink() { continue } else { metadata },
+            Err(e) => { error!(counters, "err:{} metadata:{:?}", e, path.as_ref().display()); continue },
+        };
+        let id = FileId::from_metadata(&metadata);
+        map.entry(id)
+        .or_insert_with(|| LogicalFile { id: id, len: metadata.len(), paths: Vec::new() })
+        .paths.push(path.as_ref().to_path_buf());
+    }
+    let mut files: Vec<LogicalFile> = map.into_values().map(|mut file| { file.paths.sort(); file }).collect();
+    files.sort_by(|a, b| a.path().cmp(b.path()));
+    files
+}
+
+/// Get the bytes reclaimable by removing each clone, in order, from each
+/// clone's file id and length.
+///
+/// Each clone inode counts once, no matter how many hardlinks it has,
+/// and a clone that shares an inode with an original counts zero,
+/// because removing it frees no content.
+pub fn clone_bytes<I>(clones: I, originals: &HashSet<FileId>) -> Vec<FileLen>
+where
+    I: IntoIterator<Item = (FileId, FileLen)>,
+{
+    let mut seen: HashSet<FileId> = HashSet::new();
+    clones
+    .into_iter()
+    .map(|(id, len)| if !originals.contains(&id) && seen.insert(id) { len } else { 0 })
+    .collect()
+}
+
+/// Count the bytes reclaimable by removing clones, per `clone_bytes`.
+pub fn bytes_reclaimable<I>(clones: I, originals: &HashSet<FileId>) -> FileLen
+where
+    I: IntoIterator<Item = (FileId, FileLen)>,
+{
+    clone_bytes(clones, originals).into_iter().sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Create a temp dir with these files:
+    ///
+    /// * `alpha.txt` which contains `alpha`
+    /// * `alpha_link.txt` which is a hardlink to `alpha.txt`
+    /// * `alpha_2.txt` which contains `alpha`
+    ///
+    fn setup(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join("deduplicate-files-test-inode").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("alpha.txt"), "alpha\n").unwrap();
+        fs::hard_link(dir.join("alpha.txt"), dir.join("alpha_link.txt")).unwrap();
+        fs::write(dir.join("alpha_2.txt"), "alpha\n").unwrap();
+        dir
+    }
+
+    #[test]
+    /// Test `logical_files` with a hardlink set.
+    /// Must group the hardlinks as one logical file.
+    ///
+    fn test_logical_files() {
+        let dir = setup("logical_files");
+        let files = logical_files(&Counters::default(), vec![dir.join("alpha_link.txt"), dir.join("alpha_2.txt"), dir.join("alpha.txt")]);
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].paths, vec![dir.join("alpha.txt"), dir.join("alpha_link.txt")]);
+        assert_eq!(files[1].paths, vec![dir.join("alpha_2.txt")]);
+        assert!(files[0].is_hardlinked());
+        assert!(!files[1].is_hardlinked());
+    }
+
+    #[test]
+    /// Test `bytes_reclaimable` with a hardlink set and a copy.
+    /// Must count each clone inode once, and never count an original inode.
+    ///
+    fn test_bytes_reclaimable() {
+        let dir = setup("bytes_reclaimable");
+        let files = logical_files(&Counters::default(), vec![dir.join("alpha.txt"), dir.join("alpha_link.txt"), dir.join("alpha_2.txt")]);
+        let mut originals: HashSet<FileId> = HashSet::new();
+        let clones = || files.iter().map(|file| (file.id, file.len));
+        assert_eq!(bytes_reclaimable(clones(), &originals), 12);
+        originals.insert(files[1].id);
+        assert_eq!(bytes_reclaimable(clones(), &originals), 6);
+        assert_eq!(bytes_reclaimable(clones().chain(clones()), &originals), 6);
+        assert_eq!(clone_bytes(clones().chain(clones()), &originals), vec![6, 0, 0, 0]);
+    }
+
+}
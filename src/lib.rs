@@ -0,0 +1,563 @@
+//! Deduplicate files and directories, by content.
+//!
+//! The library API is the `Scanner` builder, which scans roots with
+//! filters and hash options, then detects each `DuplicateGroup`, and the
+//! `Action` to apply to each clone. For example:
+//!
+//! ```no_run
+//! use deduplicate_directories::{Action, Scanner};
+//!
+//! let mut duplicates = Scanner::new()
+//!     .root("downloads")
+//!     .reference("archive")
+//!     .skip_hidden(true)
+//!     .scan();
+//! for group in duplicates.groups.iter_mut() {
+//!     group.apply(Action::Delete);
+//! }
+//! ```
+//!
+//! To start acting or reporting before every file is hashed, iterate
+//! `Scanner::groups`, which walks every root first, then yields the groups
+//! of each size bucket as soon as the bucket is hashed.
+//!
+//! The binary is a thin shell over the `cli` module.
+
+// Our style spells out field names, closures, unit returns, and `Default` impls.
+#![allow(
+    clippy::redundant_field_names,
+    clippy::redundant_closure,
+    clippy::unused_unit,
+    clippy::derivable_impls,
+    clippy::needless_borrows_for_generic_args,
+    clippy::bool_assert_comparison,
+)]
+
+use std::io;
+use std::path::Path;
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+use rayon::prelude::*;
+
+// First, so that its `error!` macro is in scope in the other modules.
+#[macro_use]
+mod summary;
+mod action;
+mod args;
+mod assure_paths;
+mod clap;
+pub mod cli;
+mod compare;
+mod device;
+mod diff;
+mod digest;
+mod directory;
+mod files_from;
+mod group;
+mod inode;
+mod manifest;
+mod mtime;
+mod output;
+mod read;
+mod report;
+mod scanner;
+mod script;
+mod subset;
+mod sync;
+mod tracker;
+mod tree;
+mod walk;
+
+pub use action::Action;
+pub use compare::Compare;
+pub use device::ReadOrder;
+pub use digest::Digest;
+pub use group::{DirectoryGroup, DirectoryMember, DuplicateGroup, Duplicates, Member, Role};
+pub use scanner::{Groups, Scanner};
+pub use subset::Subset;
+
+use args::Args;
+use inode::{FileId, LogicalFile};
+
+/// File length
+pub type FileLen = u64;
+
+/// Set of file path buffers
+pub(crate) type SetOfPathBuf = HashSet<PathBuf>;
+
+/// How to track files
+pub(crate) use tracker::Tracker;
+
+/// Process many paths, by calling `on_path` for each.
+///
+/// Each walk reads directories on the worker pool, and yields them in sorted
+/// order, so files are handled in path order then walk order, and the output
+/// is deterministic.
+pub(crate) fn on_paths<T: AsRef<Path>>(args: &Args, tracker: &mut Tracker, paths: &[T]) -> () {
+    for path in paths.iter() {
+        on_path(args, tracker, path);
+    }
+}
+
+/// Process one path, by walking the path then calling `on_file`.
+pub(crate) fn on_path<T: AsRef<Path>>(args: &Args, tracker: &mut Tracker, path: T) -> () {
+    if args.verbose > 0 { println!("on_path path:{:?}", path.as_ref())}
+    for entry in walk::walker(args, path.as_ref())
+    .filter(|e| e.file_type().is_file()) {
+        on_file(args, tracker, entry.path());
+    }
+}
+
+/// Process a list of files, by reading the list then calling `on_file`.
+///
+/// The list is a file, or stdin if `-`, of paths separated by newline or NUL.
+pub(crate) fn on_files_from<T: AsRef<Path>>(args: &Args, tracker: &mut Tracker, path: T) -> io::Result<()> {
+    if args.verbose > 0 { println!("on_files_from path:{:?}", path.as_ref())}
+    for file_path in files_from::read_paths(files_from::open(&path)?)? {
+        match std::fs::metadata(&file_path) {
+            Ok(metadata) => if metadata.is_file() { on_file(args, tracker, &file_path) },
+            Err(e) => error!(args.counters, "err:{} metadata:{:?}", e, file_path.display()),
+        }
+    }
+    Ok(())
+}
+
+/// Process one file, by deciding its relevance and how to handle it.
+pub(crate) fn on_file<T: AsRef<Path>>(args: &Args, tracker: &mut Tracker, path: T) -> () {
+    if args.verbose > 0 { println!("on_file path:{}", path.as_ref().display())}
+    match mtime::path_mtime_is_within(&path, args.newer_than, args.older_than) {
+        Ok(true) => (),
+        Ok(false) => { args.counters.skip(); return },
+        Err(e) => { error!(args.counters, "err:{} path_mtime_is_within:{:?}", e, path.as_ref().display()); return },
+    }
+    if args.print {
+        if let Err(e) = output::write_path(args, path.as_ref(), &mut io::stdout().lock()) {
+            error!(args.counters, "err:{} write_path:{:?}", e, path.as_ref().display());
+        }
+    }
+    tracker.insert_path(&args.counters, path.as_ref().to_path_buf());
+}
+
+/// Process one duplicate group, by calling `on_clone` for each clone.
+pub(crate) fn on_group(args: &Args, group: &mut DuplicateGroup) {
+    let original: PathBuf = match group.original() {
+        Some(path) => path.clone(),
+        None => return,
+    };
+    for member in group.members.iter_mut().filter(|member| member.role == Role::Clone) {
+        on_clone(args, &original, member);
+    }
+}
+
+/// Process one clone, by applying the action chosen by the args, then recording it.
+///
+/// A clone outside `--clones-newer-than` and `--clones-older-than` is left as is.
+/// With `--emit-script`, the action is only recorded, for the script.
+/// With `--verify`, a clone whose bytes differ from the original's is left as is.
+pub(crate) fn on_clone<T: AsRef<Path>>(args: &Args, original: T, clone: &mut Member) {
+    if args.verbose > 0 { println!("on_clone path:{:?} path:{:?}", original.as_ref(), clone.path)}
+    let action = Action::from_args(args);
+    if action == Action::None || args.print { return }
+    match mtime::path_mtime_is_within(&clone.path, args.clones_newer_than, args.clones_older_than) {
+        Ok(true) => (),
+        Ok(false) => return,
+        Err(e) => { error!(args.counters, "err:{} path_mtime_is_within:{:?}", e, clone.path.display()); return },
+    }
+    if args.verify {
+        match assure_paths::assure_paths_read_bytes_eq_with_options(original.as_ref(), clone.path.as_path(), &args.read_options) {
+            Ok(true) => (),
+            Ok(false) => {
+                error!(args.counters, "err:bytes differ verify:{:?}", clone.path.display());
+                clone.error = Some(String::from("verify: bytes differ"));
+                return
+            },
+            Err(e) => {
+                error!(args.counters, "err:{} verify:{:?}", e, clone.path.display());
+                clone.error = Some(e.to_string());
+                return
+            },
+        }
+    }
+    clone.action = action;
+    if args.emit_script.is_some() { return }
+    if let Err(e) = action.apply(original.as_ref(), clone.path.as_path()) {
+        error!(args.counters, "err:{} {:?}:{:?}", e, action, clone.path.display());
+        clone.error = Some(e.to_string());
+    }
+}
+
+/// Detect duplicates among the tracked files.
+///
+/// Paths are grouped by (dev, inode) first, so each hardlink set is one logical file.
+/// Then logical files are compared by content digest, either hashed once each
+/// and scheduled by device, or compared in lockstep within each bucket.
+/// The groups are in file length order, so the output is deterministic.
+///
+/// Equal BLAKE3 digests count as equal content, rather than a byte-for-byte
+/// comparison; for that, confirm each clone before acting with `--verify`.
+pub(crate) fn detect_duplicates(args: &Args, tracker: &Tracker) -> Duplicates {
+    let mut lens: Vec<&FileLen> = tracker.map.keys().collect();
+    lens.sort();
+    let buckets: Vec<Vec<LogicalFile>> = lens
+    .par_iter()
+    .map(|len| inode::logical_files(&args.counters, tracker.map.get(len).unwrap()))
+    .collect();
+    let digests: HashMap<FileId, Digest> = match args.compare {
+        Compare::Digest => digests_by_device(args, &buckets),
+        Compare::Lockstep => digests_by_lockstep(args, &buckets),
+    };
+    let mut duplicates = Duplicates::default();
+    for files in buckets.iter() {
+        detect_duplicates_of_len(args, files, &digests, &mut duplicates);
+    }
+    duplicates
+}
+
+/// Detect duplicates among the tracked files of one file length, i.e. one size bucket.
+///
+/// This is like `detect_duplicates`, yet it hashes only this bucket, so
+/// it's for resolving buckets one at a time, such as by `Scanner::groups`.
+pub(crate) fn detect_duplicates_of_bucket(args: &Args, paths: &SetOfPathBuf) -> Duplicates {
+    let buckets: Vec<Vec<LogicalFile>> = vec![inode::logical_files(&args.counters, paths)];
+    let digests: HashMap<FileId, Digest> = match args.compare {
+        Compare::Digest => digests_by_device(args, &buckets),
+        Compare::Lockstep => digests_by_lockstep(args, &buckets),
+    };
+    let mut duplicates = Duplicates::default();
+    detect_duplicates_of_len(args, &buckets[0], &digests, &mut duplicates);
+    duplicates
+}
+
+/// Hash every logical file that has a same-length peer once, scheduled by device.
+fn digests_by_device(args: &Args, buckets: &[Vec<LogicalFile>]) -> HashMap<FileId, Digest> {
+    let files: Vec<(FileId, PathBuf)> = buckets
+    .iter()
+    .filter(|files| files.len() > 1)
+    .flatten()
+    .map(|file| (file.id, file.path().clone()))
+    .collect();
+    let mut digests: HashMap<FileId, Digest> = HashMap::new();
+    for ((id, path), digest) in files.iter().zip(device::file_digests_by_device(&files, args.jobs_per_device, args.read_order, &args.read_options)) {
+        match digest {
+            Ok(digest) => { digests.insert(*id, digest); },
+            Err(e) => error!(args.counters, "err:{} file_digest:{:?}", e, path.display()),
+        }
+    }
+    digests
+}
+
+/// Compare the logical files of each bucket in lockstep, with each bucket a job on the worker pool.
+///
+/// Only logical files that have a duplicate in their bucket get a digest.
+fn digests_by_lockstep(args: &Args, buckets: &[Vec<LogicalFile>]) -> HashMap<FileId, Digest> {
+    buckets
+    .par_iter()
+    .filter(|files| files.len() > 1)
+    .map(|files| {
+        let paths: Vec<&PathBuf> = files.iter().map(|file| file.path()).collect();
+        files
+        .iter()
+        .zip(compare::lockstep_digests(&paths, &args.read_options))
+        .filter_map(|(file, digest)| match digest {
+            Ok(digest) => digest.map(|digest| (file.id, digest)),
+            Err(e) => { error!(args.counters, "err:{} lockstep_digests:{:?}", e, file.path().display()); None },
+        })
+        .collect::<Vec<(FileId, Digest)>>()
+    })
+    .collect::<Vec<Vec<(FileId, Digest)>>>()
+    .into_iter()
+    .flatten()
+    .collect()
+}
+
+/// Detect duplicates among logical files that have the same file length.
+///
+/// Logical files with equal digests are a group. A logical file with no peer
+/// is unique, unless it's a hardlink set and hardlinks are not deduplicated,
+/// in which case it's a group of its own paths.
+fn detect_duplicates_of_len(args: &Args, files: &[LogicalFile], digests: &HashMap<FileId, Digest>, duplicates: &mut Duplicates) {
+    let mut index: HashMap<&Digest, usize> = HashMap::new();
+    let mut by_digest: Vec<(Digest, Vec<&LogicalFile>)> = Vec::new();
+    let mut singles: Vec<&LogicalFile> = Vec::new();
+    for file in files.iter() {
+        match digests.get(&file.id) {
+            Some(digest) => match index.get(digest) {
+                Some(i) => by_digest[*i].1.push(file),
+                None => { index.insert(digest, by_digest.len()); by_digest.push((*digest, vec![file])); },
+            },
+            None => singles.push(file),
+        }
+    }
+    for (digest, mut group_files) in by_digest {
+        if group_files.len() < 2 {
+            singles.append(&mut group_files);
+            continue
+        }
+        group_files.sort_by_key(|file| rank(args, file.path()));
+        if group_files.iter().all(|file| file.paths.iter().all(|path| is_reference(args, path))) { continue }
+        duplicates.groups.push(duplicate_group(args, digest, &group_files));
+    }
+    for file in singles {
+        if file.paths.iter().all(|path| is_reference(args, path)) { continue }
+        if file.is_hardlinked() && !args.hardlinks_deduplicated {
+            match digests.get(&file.id).cloned().map(Ok).unwrap_or_else(|| digest::file_digest(file.path(), &args.read_options)) {
+                Ok(digest) => { duplicates.groups.push(duplicate_group(args, digest, &[file])); continue },
+                Err(e) => error!(args.counters, "err:{} file_digest:{:?}", e, file.path().display()),
+            }
+        }
+        duplicates.uniques.append(&mut Member::from_logical_file(file, Role::Unique));
+    }
+}
+
+/// Create a duplicate group from logical files, where the first is the original.
+///
+/// The other paths of the original are hardlinks, which are originals too
+/// if hardlinks are deduplicated, or else clones that reclaim no bytes.
+///
+/// The files are in rank order, so files within `--reference` roots come
+/// first, and each of their paths is an original, so it's never touched.
+fn duplicate_group(args: &Args, digest: Digest, files: &[&LogicalFile]) -> DuplicateGroup {
+    let n = std::cmp::max(1, files.iter().take_while(|file| is_reference(args, file.path())).count());
+    let mut members: Vec<Member> = Vec::new();
+    for (i, file) in files.iter().enumerate() {
+        let mut file_members = Member::from_logical_file(file, if i < n { Role::Original } else { Role::Clone });
+        if i == 0 && !args.hardlinks_deduplicated {
+            file_members.iter_mut().skip(1).for_each(|member| member.role = Role::Clone);
+        }
+        file_members.iter_mut().filter(|member| is_reference(args, &member.path)).for_each(|member| member.role = Role::Original);
+        members.append(&mut file_members);
+    }
+    let originals: HashSet<FileId> = files.iter().take(n).map(|file| file.id).collect();
+    DuplicateGroup {
+        digest: digest,
+        size: files[0].len,
+        bytes_reclaimable: inode::bytes_reclaimable(files.iter().skip(n).map(|file| (file.id, file.len)), &originals),
+        members: members,
+    }
+}
+
+/// Rank a path for choosing the original: the earliest `--reference` root wins,
+/// then the earliest path argument, then the path.
+///
+/// Roots and paths are compared in canonical form, so e.g. `./archive/x`
+/// is within the root `archive`.
+pub(crate) fn rank(args: &Args, path: &Path) -> (usize, PathBuf) {
+    let canonical_path = canonical(path);
+    let roots = args.reference.iter().chain(args.paths.iter());
+    let i = roots.clone().position(|root| canonical_path.starts_with(canonical(root))).unwrap_or(roots.count());
+    (i, path.to_path_buf())
+}
+
+/// Is a path within a `--reference` root, so it's only ever an original?
+pub(crate) fn is_reference(args: &Args, path: &Path) -> bool {
+    if args.reference.is_empty() { return false }
+    let canonical_path = canonical(path);
+    args.reference.iter().any(|root| canonical_path.starts_with(canonical(root)))
+}
+
+/// Get the canonical form of a path, or the path as is if it can't be resolved.
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    /// Test `on_path` via one directory that contains these files:
+    /// 
+    /// * `alpha.txt` which contains `alpha` (len == 6)
+    /// * `bravo.txt` which contains `bravo` (len == 6)
+    /// * `charlie.txt` which contains `charlie` (len == 8)
+    /// 
+    fn test_on_path() {
+        let args = Args::default();
+        let mut tracker = Tracker::new();
+        let path_buf: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "main", "on_path"].iter().collect();
+        let path_buf_alpha: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "main", "on_path", "alpha.txt"].iter().collect();
+        let path_buf_bravo: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "main", "on_path", "bravo.txt"].iter().collect();
+        let path_buf_charlie: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "main", "on_path", "charlie.txt"].iter().collect();
+        on_path(&args, &mut tracker, path_buf);
+        assert!(tracker.contains_path(&path_buf_alpha));
+        assert!(tracker.contains_path(&path_buf_bravo));
+        assert!(tracker.contains_path(&path_buf_charlie));
+    }
+
+    #[test]
+    /// Test `on_paths` via two directories.
+    /// Must track the files of each directory.
+    ///
+    fn test_on_paths() {
+        let args = Args::default();
+        let mut tracker = Tracker::new();
+        let paths: Vec<PathBuf> = vec![
+            [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates", "a"].iter().collect(),
+            [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates", "b"].iter().collect(),
+        ];
+        let path_buf_a_alpha: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates", "a", "alpha.txt"].iter().collect();
+        let path_buf_b_alpha: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates", "b", "alpha.txt"].iter().collect();
+        on_paths(&args, &mut tracker, &paths);
+        assert!(tracker.contains_path(&path_buf_a_alpha));
+        assert!(tracker.contains_path(&path_buf_b_alpha));
+    }
+
+    #[test]
+    /// Test `on_file` via one file.
+    fn test_on_file() {
+        let args = Args::default();
+        let mut tracker = Tracker::new();
+        let path_buf: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "main", "on_path", "alpha.txt"].iter().collect();
+        on_file(&args, &mut tracker, &path_buf);
+        assert!(tracker.contains_path(&path_buf));
+    }
+
+    #[test]
+    /// Test `on_file` via one file that is older than `--newer-than`.
+    /// Must skip the file.
+    fn test_on_file_x_newer_than() {
+        let args = Args { newer_than: Some(std::time::SystemTime::now()), ..Args::default() };
+        let mut tracker = Tracker::new();
+        let path_buf: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "main", "on_path", "alpha.txt"].iter().collect();
+        on_file(&args, &mut tracker, &path_buf);
+        assert!(!tracker.contains_path(&path_buf));
+    }
+
+    #[test]
+    /// Test `on_files_from` via one list that contains these lines:
+    ///
+    /// * `alpha.txt` which is a file
+    /// * `missing.txt` which does not exist
+    ///
+    fn test_on_files_from() {
+        let args = Args::default();
+        let mut tracker = Tracker::new();
+        let path_buf_alpha: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "main", "on_path", "alpha.txt"].iter().collect();
+        let path_buf_missing: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "main", "on_path", "missing.txt"].iter().collect();
+        let path_buf: PathBuf = std::env::temp_dir().join("deduplicate-files-test-on-files-from.txt");
+        std::fs::write(&path_buf, format!("{}\n{}\n", path_buf_alpha.display(), path_buf_missing.display())).unwrap();
+        on_files_from(&args, &mut tracker, &path_buf).unwrap();
+        assert!(tracker.contains_path(&path_buf_alpha));
+        assert!(!tracker.contains_path(&path_buf_missing));
+    }
+
+    #[test]
+    /// Test `detect_duplicates` via these files:
+    /// 
+    /// * `a/alpha.txt` which contains `alpha`
+    /// * `a/bravo.txt` which contains `bravo`
+    /// * `b/alpha.txt` which contains `alpha`
+    /// * `b/alpha_2.txt` which contains `alpha`
+    /// 
+    fn test_detect_duplicates() {
+        let a_path_buf: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates", "a"].iter().collect();
+        let b_path_buf: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates", "b"].iter().collect();
+        let args = Args { paths: vec![a_path_buf.clone(), b_path_buf.clone()], ..Args::default() };
+        let mut tracker = Tracker::new();
+        on_paths(&args, &mut tracker, &args.paths);
+        let duplicates = detect_duplicates(&args, &tracker);
+        assert_eq!(duplicates.groups.len(), 1);
+        let group = &duplicates.groups[0];
+        assert_eq!(group.size, 6);
+        assert_eq!(group.bytes_reclaimable, 12);
+        assert_eq!(group.original(), Some(&a_path_buf.join("alpha.txt")));
+        assert_eq!(
+            group.clones().map(|member| member.path.clone()).collect::<Vec<PathBuf>>(),
+            vec![b_path_buf.join("alpha.txt"), b_path_buf.join("alpha_2.txt")]
+        );
+        assert_eq!(duplicates.uniques.len(), 1);
+        assert_eq!(duplicates.uniques[0].path, a_path_buf.join("bravo.txt"));
+    }
+
+    #[test]
+    /// Test `detect_duplicates` with `--follow-symlinks`, via a temp dir with these files:
+    ///
+    /// * `alpha.txt` which contains `alpha`
+    /// * `alpha_2.txt` which contains `alpha`
+    /// * `alpha_link.txt` which is a symlink to `alpha.txt`
+    ///
+    /// The dir is a root twice, spelled as `dir` and `dir/.`.
+    /// Must track each file once, and never make one file both original and clone.
+    ///
+    fn test_detect_duplicates_x_symlink() {
+        let dir = std::env::temp_dir().join("deduplicate-files-test-lib-symlink");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("alpha.txt"), "alpha\n").unwrap();
+        std::fs::write(dir.join("alpha_2.txt"), "alpha\n").unwrap();
+        std::os::unix::fs::symlink(dir.join("alpha.txt"), dir.join("alpha_link.txt")).unwrap();
+        let args = Args { paths: vec![dir.clone(), dir.join(".")], follow_symlinks: true, ..Args::default() };
+        let mut tracker = Tracker::new();
+        on_paths(&args, &mut tracker, &args.paths);
+        let duplicates = detect_duplicates(&args, &tracker);
+        assert_eq!(duplicates.groups.len(), 1);
+        let group = &duplicates.groups[0];
+        assert_eq!(group.original(), Some(&dir.join("alpha.txt")));
+        assert_eq!(
+            group.clones().map(|member| member.path.clone()).collect::<Vec<PathBuf>>(),
+            vec![dir.join("alpha_2.txt")]
+        );
+        assert_eq!(group.members.len(), 2);
+    }
+
+    #[test]
+    /// Test `detect_duplicates` with `--reference zarchive .`, via a temp dir with these files:
+    ///
+    /// * `alpha.txt` which contains `alpha`
+    /// * `zarchive/alpha.txt` which contains `alpha`
+    ///
+    /// The reference root is nested in the path root, and spelled differently.
+    /// Must walk the reference root once, and keep its file as the original.
+    ///
+    fn test_detect_duplicates_x_reference_x_nested() {
+        let dir = std::env::temp_dir().join("deduplicate-files-test-lib-reference-nested");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(dir.join("zarchive")).unwrap();
+        std::fs::write(dir.join("alpha.txt"), "alpha\n").unwrap();
+        std::fs::write(dir.join("zarchive").join("alpha.txt"), "alpha\n").unwrap();
+        let args = Args { paths: vec![dir.join(".")], reference: vec![dir.join("zarchive")], ..Args::default() };
+        assert_eq!(walk::walker(&args, dir.join(".")).filter(|e| e.file_type().is_file()).count(), 1);
+        let mut tracker = Tracker::new();
+        on_paths(&args, &mut tracker, &args.reference);
+        on_paths(&args, &mut tracker, &args.paths);
+        let duplicates = detect_duplicates(&args, &tracker);
+        assert_eq!(duplicates.groups.len(), 1);
+        let group = &duplicates.groups[0];
+        assert_eq!(group.original(), Some(&dir.join("zarchive").join("alpha.txt")));
+        assert_eq!(
+            group.clones().map(|member| member.path.clone()).collect::<Vec<PathBuf>>(),
+            vec![dir.join(".").join("alpha.txt")]
+        );
+    }
+
+    #[test]
+    /// Test `detect_duplicates` with `--reference`, via the same files,
+    /// where `b` is the reference root.
+    /// Must keep each file of `b` as an original, and skip `b` in the uniques.
+    ///
+    fn test_detect_duplicates_x_reference() {
+        let a_path_buf: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates", "a"].iter().collect();
+        let b_path_buf: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates", "b"].iter().collect();
+        let args = Args { paths: vec![a_path_buf.clone()], reference: vec![b_path_buf.clone()], ..Args::default() };
+        let mut tracker = Tracker::new();
+        on_paths(&args, &mut tracker, &args.reference);
+        on_paths(&args, &mut tracker, &args.paths);
+        let duplicates = detect_duplicates(&args, &tracker);
+        assert_eq!(duplicates.groups.len(), 1);
+        let group = &duplicates.groups[0];
+        assert_eq!(group.bytes_reclaimable, 6);
+        assert_eq!(group.original(), Some(&b_path_buf.join("alpha.txt")));
+        assert_eq!(
+            group.members.iter().filter(|member| member.role == Role::Original).count(),
+            2
+        );
+        assert_eq!(
+            group.clones().map(|member| member.path.clone()).collect::<Vec<PathBuf>>(),
+            vec![a_path_buf.join("alpha.txt")]
+        );
+        assert_eq!(duplicates.uniques.len(), 1);
+        assert_eq!(duplicates.uniques[0].path, a_path_buf.join("bravo.txt"));
+    }
+
+}
@@ -0,0 +1,228 @@
+//! Manifests of scanned files, to deduplicate against files that are offline.
+//!
+//! A manifest is NDJSON, i.e. one JSON object per line, with each file's
+//! path, size, mtime in seconds since the epoch, and content digest.
+//!
+//! To compare live files with a manifest, we hash only the live files
+//! whose size is in the manifest, because any other file can't match.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use rayon::prelude::*;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use serde_json::json;
+use crate::{FileLen, Tracker};
+use crate::args::Args;
+use crate::digest::{self, Digest};
+use crate::output::Format;
+
+/// One file of a manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Entry {
+    pub path: String,
+    pub size: FileLen,
+    pub mtime: Option<u64>,
+    #[serde(serialize_with = "serialize_digest", deserialize_with = "deserialize_digest")]
+    pub digest: Digest,
+}
+
+/// A live file whose content is in the manifest, with the paths in the manifest.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestMatch {
+    pub path: PathBuf,
+    pub size: FileLen,
+    pub digest: Digest,
+    pub entries: Vec<String>,
+}
+
+/// Hash the tracked files, on the worker pool, into entries sorted by path.
+pub fn entries(args: &Args, tracker: &Tracker) -> Vec<Entry> {
+    let mut entries: Vec<Entry> = tracker.map
+    .iter()
+    .flat_map(|(size, paths)| paths.iter().map(move |path| (*size, path)))
+    .collect::<Vec<(FileLen, &PathBuf)>>()
+    .into_par_iter()
+    .filter_map(|(size, path)| {
+        let digest = match digest::file_digest(path, &args.read_options) {
+            Ok(digest) => digest,
+            Err(e) => { error!(args.counters, "err:{} file_digest:{:?}", e, path); return None },
+        };
+        let mtime = std::fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+        .map(|d| d.as_secs());
+        Some(Entry { path: path.to_string_lossy().into_owned(), size: size, mtime: mtime, digest: digest })
+    })
+    .collect();
+    entries.sort_by(|x, y| x.path.cmp(&y.path));
+    entries
+}
+
+/// Write a manifest file, one entry per line.
+pub fn write_manifest<T: AsRef<Path>>(entries: &[Entry], path: T) -> io::Result<()> {
+    let mut w = BufWriter::new(File::create(path)?);
+    for entry in entries.iter() {
+        serde_json::to_writer(&mut w, entry)?;
+        writeln!(w)?;
+    }
+    w.flush()
+}
+
+/// Read a manifest file, and skip blank lines.
+pub fn read_manifest<T: AsRef<Path>>(path: T) -> io::Result<Vec<Entry>> {
+    let path = path.as_ref();
+    let mut entries: Vec<Entry> = Vec::new();
+    for (i, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() { continue }
+        let entry = serde_json::from_str::<Entry>(&line)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("manifest:{:?}:{} {}", path, i + 1, e)))?;
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+/// Find the tracked files whose content is in the manifest, sorted by path.
+pub fn compare(args: &Args, tracker: &Tracker, entries: &[Entry]) -> Vec<ManifestMatch> {
+    let sizes: HashSet<FileLen> = entries.iter().map(|entry| entry.size).collect();
+    let mut contents: HashMap<Digest, Vec<&str>> = HashMap::new();
+    for entry in entries.iter() {
+        contents.entry(entry.digest).or_default().push(&entry.path);
+    }
+    let mut matches: Vec<ManifestMatch> = tracker.map
+    .iter()
+    .filter(|(size, _)| sizes.contains(size))
+    .flat_map(|(size, paths)| paths.iter().map(move |path| (*size, path)))
+    .collect::<Vec<(FileLen, &PathBuf)>>()
+    .into_par_iter()
+    .filter_map(|(size, path)| {
+        let digest = match digest::file_digest(path, &args.read_options) {
+            Ok(digest) => digest,
+            Err(e) => { error!(args.counters, "err:{} file_digest:{:?}", e, path); return None },
+        };
+        contents.get(&digest).map(|paths| ManifestMatch {
+            path: path.clone(),
+            size: size,
+            digest: digest,
+            entries: paths.iter().map(|x| x.to_string()).collect(),
+        })
+    })
+    .collect();
+    matches.sort_by(|x, y| x.path.cmp(&y.path));
+    matches
+}
+
+/// Write the matches, as one JSON array, or as NDJSON with one match per
+/// line, or else as text.
+///
+/// Text lines are `Duplicate:` with the live path and each manifest path.
+pub fn write_matches<W: Write>(args: &Args, matches: &[ManifestMatch], w: &mut W) -> io::Result<()> {
+    let values = matches
+    .iter()
+    .map(|x| json!({ "path": x.path.to_string_lossy(), "size": x.size, "digest": x.digest.to_hex().as_str(), "manifest": x.entries }));
+    match args.format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *w, &values.collect::<Vec<serde_json::Value>>())?;
+            writeln!(w)
+        },
+        Format::Ndjson => {
+            for value in values {
+                serde_json::to_writer(&mut *w, &value)?;
+                writeln!(w)?;
+            }
+            Ok(())
+        },
+        _ => {
+            for x in matches.iter() {
+                for entry in x.entries.iter() {
+                    writeln!(w, "Duplicate: {:?} {:?}", x.path, entry)?;
+                }
+            }
+            Ok(())
+        },
+    }
+}
+
+fn serialize_digest<S: Serializer>(digest: &Digest, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&digest.to_hex())
+}
+
+fn deserialize_digest<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Digest, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    Digest::from_hex(&s).map_err(|_| serde::de::Error::custom(format!("digest:{}", s)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test `write_manifest` then `read_manifest` then `compare`, via these files:
+    ///
+    /// * `a/alpha.txt` which contains `alpha`
+    /// * `a/bravo.txt` which contains `bravo`
+    /// * `a/charlie.txt` which contains `charlie`
+    /// * `a/delta.txt` which contains `delta`
+    /// * `b/alpha.txt` which contains `alpha`
+    /// * `b/renamed/bravo_2.txt` which contains `bravo`
+    /// * `b/charlie.txt` which contains `CHARLIE`
+    /// * `b/echo.txt` which contains `echo`
+    ///
+    /// The manifest is of `a`, and the live files are `b`.
+    /// Must round-trip the manifest, and match the files of `b` whose content is in `a`.
+    ///
+    fn test_manifest() {
+        let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "diff"].iter().collect();
+        let args = Args::default();
+        let mut tracker = Tracker::new();
+        crate::on_paths(&args, &mut tracker, &[dir.join("a")]);
+        let entries = entries(&args, &tracker);
+        assert_eq!(entries.len(), 4);
+        let out = std::env::temp_dir().join("deduplicate-files-test-manifest.ndjson");
+        write_manifest(&entries, &out).unwrap();
+        assert_eq!(read_manifest(&out).unwrap(), entries);
+        let mut tracker = Tracker::new();
+        crate::on_paths(&args, &mut tracker, &[dir.join("b")]);
+        let matches = compare(&args, &tracker, &entries);
+        let found: Vec<(PathBuf, Vec<String>)> = matches.into_iter().map(|x| (x.path, x.entries)).collect();
+        assert_eq!(found, vec![
+            (dir.join("b").join("alpha.txt"), vec![dir.join("a").join("alpha.txt").to_string_lossy().into_owned()]),
+            (dir.join("b").join("renamed").join("bravo_2.txt"), vec![dir.join("a").join("bravo.txt").to_string_lossy().into_owned()]),
+        ]);
+    }
+
+    #[test]
+    /// Test `read_manifest` with an invalid digest.
+    /// Must err with the line number.
+    ///
+    fn test_read_manifest_x_invalid() {
+        let out = std::env::temp_dir().join("deduplicate-files-test-manifest-invalid.ndjson");
+        std::fs::write(&out, "\n{\"path\":\"x\",\"size\":1,\"mtime\":null,\"digest\":\"xyz\"}\n").unwrap();
+        let e = read_manifest(&out).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert!(e.to_string().contains(":2 "));
+    }
+
+    #[test]
+    /// Test `write_matches` with `Format::Ndjson`.
+    /// Must write one compact match per line.
+    ///
+    fn test_write_matches_x_ndjson() {
+        let digest = blake3::hash(b"alpha\n");
+        let matches = vec![
+            ManifestMatch { path: PathBuf::from("b/alpha.txt"), size: 6, digest: digest, entries: vec![String::from("a/alpha.txt")] },
+            ManifestMatch { path: PathBuf::from("b/alpha_2.txt"), size: 6, digest: digest, entries: vec![String::from("a/alpha.txt")] },
+        ];
+        let mut w: Vec<u8> = Vec::new();
+        write_matches(&Args { format: Format::Ndjson, ..Args::default() }, &matches, &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        let values: Vec<serde_json::Value> = s.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[1], json!({ "path": "b/alpha_2.txt", "size": 6, "digest": digest.to_hex().as_str(), "manifest": ["a/alpha.txt"] }));
+    }
+
+}
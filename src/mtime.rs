@@ -0,0 +1,233 @@
+//! Modification-time filters.
+//!
+//! A filter time can be an absolute date, such as `2020-01-31`
+//! or `2020-01-31T12:00:00`, which we interpret as UTC, with a year
+//! from 1970 to 9999.
+//!
+//! A filter time can be a duration before now, such as `30d`,
+//! using the units `s` seconds, `m` minutes, `h` hours, `d` days,
+//! `w` weeks, and `y` years of 365 days.
+
+use std::{fs,io};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Parse a filter time, either an absolute date or a duration before now.
+pub fn parse_time(s: &str) -> io::Result<SystemTime> {
+    match s.contains('-') {
+        true => parse_date(s),
+        false => {
+            let duration = parse_duration(s)?;
+            SystemTime::now().checked_sub(duration).ok_or_else(|| invalid_input(s))
+        }
+    }
+}
+
+/// Parse a duration such as `30d` into a `Duration`.
+pub fn parse_duration(s: &str) -> io::Result<Duration> {
+    let s = s.trim();
+    if s.len() < 2 { return Err(invalid_input(s)) }
+    let (number, unit) = s.split_at(s.len() - 1);
+    let number: u64 = number.parse().map_err(|_| invalid_input(s))?;
+    let seconds: u64 = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 60 * 60 * 24,
+        "w" => 60 * 60 * 24 * 7,
+        "y" => 60 * 60 * 24 * 365,
+        _ => return Err(invalid_input(s)),
+    };
+    number.checked_mul(seconds).map(Duration::from_secs).ok_or_else(|| invalid_input(s))
+}
+
+/// Parse a UTC date such as `2020-01-31` or `2020-01-31T12:00:00` into a `SystemTime`.
+pub fn parse_date(s: &str) -> io::Result<SystemTime> {
+    let s = s.trim();
+    let (date, time) = match s.find(['T', ' ']) {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    let ymd = parse_fields(date, '-', 3).ok_or_else(|| invalid_input(s))?;
+    if !(1970..=9999).contains(&ymd[0]) { return Err(invalid_input(s)) }
+    let (year, month, day) = (ymd[0] as i64, ymd[1], ymd[2]);
+    if !(1..=12).contains(&month) || !(1..=days_in_month(year, month)).contains(&day) { return Err(invalid_input(s)) }
+    let hms = match time {
+        Some(time) => parse_fields(time, ':', 3).ok_or_else(|| invalid_input(s))?,
+        None => vec![0, 0, 0],
+    };
+    if hms[0] > 23 || hms[1] > 59 || hms[2] > 59 { return Err(invalid_input(s)) }
+    let seconds = (days_from_civil(year, month, day) as u64).checked_mul(86400)
+    .and_then(|seconds| seconds.checked_add(hms[0] * 3600 + hms[1] * 60 + hms[2]))
+    .ok_or_else(|| invalid_input(s))?;
+    UNIX_EPOCH.checked_add(Duration::from_secs(seconds)).ok_or_else(|| invalid_input(s))
+}
+
+/// Is a modification time within the optional bounds?
+///
+/// The bound `newer_than` is exclusive, and `older_than` is exclusive.
+pub fn mtime_is_within(mtime: SystemTime, newer_than: Option<SystemTime>, older_than: Option<SystemTime>) -> bool {
+    if let Some(t) = newer_than { if mtime <= t { return false } }
+    if let Some(t) = older_than { if mtime >= t { return false } }
+    true
+}
+
+/// Is a path's modification time within the optional bounds?
+pub fn path_mtime_is_within<T: AsRef<Path>>(path: T, newer_than: Option<SystemTime>, older_than: Option<SystemTime>) -> io::Result<bool> {
+    if newer_than.is_none() && older_than.is_none() { return Ok(true) }
+    let mtime = fs::metadata(path)?.modified()?;
+    Ok(mtime_is_within(mtime, newer_than, older_than))
+}
+
+/// Format seconds since the epoch as a UTC date and time such as `2020-01-31T12:00:00`.
+pub fn format_time(seconds: u64) -> String {
+    let (year, month, day) = civil_from_days((seconds / 86400) as i64);
+    let s = seconds % 86400;
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}", year, month, day, s / 3600, s % 3600 / 60, s % 60)
+}
+
+/// Parse exactly `n` unsigned fields separated by `separator`.
+fn parse_fields(s: &str, separator: char, n: usize) -> Option<Vec<u64>> {
+    let fields: Vec<u64> = s.split(separator).map(|x| x.parse().ok()).collect::<Option<Vec<u64>>>()?;
+    match fields.len() == n {
+        true => Some(fields),
+        false => None,
+    }
+}
+
+/// Days in a month of a proleptic Gregorian year.
+fn days_in_month(year: i64, month: u64) -> u64 {
+    match month {
+        2 => if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) { 29 } else { 28 },
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+///
+/// See http://howardhinnant.github.io/date_algorithms.html
+fn days_from_civil(year: i64, month: u64, day: u64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = (if year >= 0 { year } else { year - 399 }) / 400;
+    let yoe = (year - era * 400) as u64;
+    let mp = (month + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe as i64 - 719468
+}
+
+/// Proleptic Gregorian date for days since 1970-01-01.
+///
+/// See http://howardhinnant.github.io/date_algorithms.html
+fn civil_from_days(days: i64) -> (i64, u64, u64) {
+    let z = days + 719468;
+    let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
+    let doe = (z - era * 146097) as u64;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe as i64 + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn invalid_input(s: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("time:{}", s),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test `parse_duration` with each unit.
+    /// Must be the count of seconds.
+    ///
+    fn test_parse_duration_x_units() {
+        assert_eq!(parse_duration("5s").unwrap(), Duration::from_secs(5));
+        assert_eq!(parse_duration("5m").unwrap(), Duration::from_secs(300));
+        assert_eq!(parse_duration("5h").unwrap(), Duration::from_secs(18000));
+        assert_eq!(parse_duration("30d").unwrap(), Duration::from_secs(2592000));
+        assert_eq!(parse_duration("2w").unwrap(), Duration::from_secs(1209600));
+        assert_eq!(parse_duration("1y").unwrap(), Duration::from_secs(31536000));
+    }
+
+    #[test]
+    /// Test `parse_duration` with invalid input.
+    /// Must err.
+    ///
+    fn test_parse_duration_x_invalid() {
+        assert_eq!(parse_duration("").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_duration("d").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_duration("30").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_duration("30x").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    /// Test `parse_date` with dates and times.
+    /// Must be the seconds since the epoch.
+    ///
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970-01-01").unwrap(), UNIX_EPOCH);
+        assert_eq!(parse_date("1970-01-02").unwrap(), UNIX_EPOCH + Duration::from_secs(86400));
+        assert_eq!(parse_date("2000-03-01").unwrap(), UNIX_EPOCH + Duration::from_secs(951868800));
+        assert_eq!(parse_date("2000-03-01T01:02:03").unwrap(), UNIX_EPOCH + Duration::from_secs(951872523));
+        assert_eq!(parse_date("2000-02-29").unwrap(), UNIX_EPOCH + Duration::from_secs(951782400));
+    }
+
+    #[test]
+    /// Test `parse_date` with invalid input.
+    /// Must err.
+    ///
+    fn test_parse_date_x_invalid() {
+        assert_eq!(parse_date("2000-13-01").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("2000-01").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("2000-01-01T25:00:00").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("1969-12-31").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("2000-02-31").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("2000-04-31").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("1900-02-29").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("10000-01-01").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("300000000000-01-01").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("99999999999999999-01-01").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_date("9999-12-31T23:59:59").unwrap(), UNIX_EPOCH + Duration::from_secs(253402300799));
+    }
+
+    #[test]
+    /// Test `format_time` with dates and times.
+    /// Must round-trip with `parse_date`.
+    ///
+    fn test_format_time() {
+        assert_eq!(format_time(0), "1970-01-01T00:00:00");
+        assert_eq!(format_time(951872523), "2000-03-01T01:02:03");
+        assert_eq!(parse_date(&format_time(1234567890)).unwrap(), UNIX_EPOCH + Duration::from_secs(1234567890));
+    }
+
+    #[test]
+    /// Test `parse_time` with a duration.
+    /// Must be before now.
+    ///
+    fn test_parse_time_x_duration() {
+        let t = parse_time("1d").unwrap();
+        assert!(t < SystemTime::now() - Duration::from_secs(86000));
+    }
+
+    #[test]
+    /// Test `mtime_is_within` with bounds.
+    ///
+    fn test_mtime_is_within() {
+        let t = UNIX_EPOCH + Duration::from_secs(1000);
+        let before = Some(UNIX_EPOCH + Duration::from_secs(500));
+        let after = Some(UNIX_EPOCH + Duration::from_secs(1500));
+        assert!(mtime_is_within(t, None, None));
+        assert!(mtime_is_within(t, before, after));
+        assert!(!mtime_is_within(t, after, None));
+        assert!(!mtime_is_within(t, None, before));
+    }
+
+}
@@ -0,0 +1,477 @@
+//! Output of duplicate groups.
+//!
+//! The text format prints paths, chosen by `--find-cloned`, `--find-clones`,
+//! and `--find-uniques`, or else prints each clone with its original.
+//! Duplicate directories, from `--directories`, come before files, and
+//! subset directories, from `--subsets`, come after.
+//! Each path is written as its raw bytes, terminated by newline, or by NUL
+//! with `--print0`, so any file name is safe to pipe into `xargs -0`.
+//!
+//! The JSON format prints one document with every group, and the NDJSON
+//! format prints one group per line, for dashboards and follow-up scripts.
+//!
+//! The CSV and TSV formats print one row per file, for spreadsheets.
+//!
+//! The fdupes and jdupes-json formats match the output of `fdupes` and of
+//! `jdupes -j`, so existing scripts that parse them keep working. They list
+//! file groups only, so with `--directories`, the file groups under clone
+//! directories are left as is, rather than collapsed into directory groups.
+//!
+//! The JSON and NDJSON formats include the summary statistics; for the
+//! other formats, the summary prints as text to stderr.
+
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::UNIX_EPOCH;
+use serde_json::json;
+use crate::args::Args;
+use crate::group::{DirectoryGroup, DuplicateGroup, Duplicates, Member, Role};
+use crate::summary::Summary;
+
+/// Output format.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Ndjson,
+    Csv,
+    Tsv,
+    Fdupes,
+    JdupesJson,
+}
+
+impl Default for Format {
+    fn default() -> Self {
+        Format::Text
+    }
+}
+
+impl FromStr for Format {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Format::Text),
+            "json" => Ok(Format::Json),
+            "ndjson" => Ok(Format::Ndjson),
+            "csv" => Ok(Format::Csv),
+            "tsv" => Ok(Format::Tsv),
+            "fdupes" => Ok(Format::Fdupes),
+            "jdupes-json" => Ok(Format::JdupesJson),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("format:{}", s))),
+        }
+    }
+}
+
+impl Format {
+
+    /// Does the format include the summary statistics?
+    pub fn has_summary(self) -> bool {
+        matches!(self, Format::Json | Format::Ndjson)
+    }
+
+    /// Does the format include the duplicate directories?
+    pub fn has_directories(self) -> bool {
+        !matches!(self, Format::Fdupes | Format::JdupesJson)
+    }
+
+}
+
+/// Write the duplicates in the format chosen by the args.
+///
+/// With `--by-directory`, write the totals per directory instead, as JSON,
+/// NDJSON, or else as text.
+pub fn write_duplicates<W: Write>(args: &Args, duplicates: &Duplicates, summary: &Summary, w: &mut W) -> io::Result<()> {
+    if args.by_directory {
+        return match args.format {
+            Format::Json => crate::directory::write_json(args, duplicates, w),
+            Format::Ndjson => crate::directory::write_ndjson(args, duplicates, w),
+            _ => crate::directory::write_text(args, duplicates, w),
+        }
+    }
+    match args.format {
+        Format::Text => write_text(args, duplicates, w),
+        Format::Json => write_json(args, duplicates, summary, w),
+        Format::Ndjson => write_ndjson(args, duplicates, summary, w),
+        Format::Csv => write_csv(args, duplicates, w, b','),
+        Format::Tsv => write_csv(args, duplicates, w, b'\t'),
+        Format::Fdupes => write_fdupes(duplicates, w),
+        Format::JdupesJson => write_jdupes_json(duplicates, w),
+    }
+}
+
+/// Write the duplicates as text.
+pub fn write_text<W: Write>(args: &Args, duplicates: &Duplicates, w: &mut W) -> io::Result<()> {
+    let find = args.find_cloned || args.find_clones || args.find_uniques;
+    for group in duplicates.directories.iter() {
+        for member in group.members.iter() {
+            match member.role {
+                Role::Original if args.find_cloned => write_path(args, &member.path, w)?,
+                Role::Clone if args.find_clones => write_path(args, &member.path, w)?,
+                Role::Clone if !find => writeln!(w, "Clone directory: {:?} {:?}", group.original().unwrap(), member.path)?,
+                _ => (),
+            }
+        }
+    }
+    for group in duplicates.groups.iter() {
+        for member in group.members.iter() {
+            match member.role {
+                Role::Original if args.find_cloned => write_path(args, &member.path, w)?,
+                Role::Clone if args.find_clones => write_path(args, &member.path, w)?,
+                Role::Clone if !find => writeln!(w, "Clone: {:?} {:?}", group.original().unwrap(), member.path)?,
+                _ => (),
+            }
+        }
+    }
+    if args.find_uniques {
+        for member in duplicates.uniques.iter() {
+            write_path(args, &member.path, w)?;
+        }
+    }
+    if !find {
+        for subset in duplicates.subsets.iter() {
+            writeln!(w, "Subset: {:?} {:?} {:.1}%", subset.path, subset.superset, subset.overlap)?;
+        }
+    }
+    Ok(())
+}
+
+/// Write one path as its raw bytes, terminated by NUL with `--print0`, or else by newline.
+pub fn write_path<W: Write>(args: &Args, path: &Path, w: &mut W) -> io::Result<()> {
+    w.write_all(path.as_os_str().as_bytes())?;
+    w.write_all(if args.print0 { b"\0" } else { b"\n" })
+}
+
+/// Write the duplicates as one JSON document.
+pub fn write_json<W: Write>(args: &Args, duplicates: &Duplicates, summary: &Summary, w: &mut W) -> io::Result<()> {
+    let uniques: &[Member] = if args.find_uniques { &duplicates.uniques } else { &[] };
+    serde_json::to_writer_pretty(&mut *w, &json!({
+        "directories": duplicates.directories,
+        "groups": duplicates.groups,
+        "uniques": uniques,
+        "subsets": duplicates.subsets,
+        "summary": summary,
+    }))?;
+    writeln!(w)
+}
+
+/// Write the duplicates as NDJSON, one group per line, then one unique per line,
+/// then the summary.
+pub fn write_ndjson<W: Write>(args: &Args, duplicates: &Duplicates, summary: &Summary, w: &mut W) -> io::Result<()> {
+    for group in duplicates.directories.iter() {
+        write_ndjson_directory_group(group, w)?;
+    }
+    for group in duplicates.groups.iter() {
+        write_ndjson_group(group, w)?;
+    }
+    if args.find_uniques {
+        for member in duplicates.uniques.iter() {
+            serde_json::to_writer(&mut *w, &json!({ "type": "unique", "member": member }))?;
+            writeln!(w)?;
+        }
+    }
+    for subset in duplicates.subsets.iter() {
+        serde_json::to_writer(&mut *w, &json!({ "type": "subset", "subset": subset }))?;
+        writeln!(w)?;
+    }
+    serde_json::to_writer(&mut *w, &json!({ "type": "summary", "summary": summary }))?;
+    writeln!(w)
+}
+
+/// Write one group as one NDJSON line.
+pub fn write_ndjson_group<W: Write>(group: &DuplicateGroup, w: &mut W) -> io::Result<()> {
+    serde_json::to_writer(&mut *w, &json!({ "type": "group", "group": group }))?;
+    writeln!(w)
+}
+
+/// Write one directory group as one NDJSON line.
+pub fn write_ndjson_directory_group<W: Write>(group: &DirectoryGroup, w: &mut W) -> io::Result<()> {
+    serde_json::to_writer(&mut *w, &json!({ "type": "directory_group", "group": group }))?;
+    writeln!(w)
+}
+
+/// Write the duplicates as CSV or TSV, one row per file.
+///
+/// The columns are group id, role, size, digest, path, and mtime.
+/// A unique has no group id and no digest. A directory group has an id
+/// that starts with `d`, and its size is the total of its files. A field
+/// that contains the delimiter, a quote, or a newline is quoted.
+pub fn write_csv<W: Write>(args: &Args, duplicates: &Duplicates, w: &mut W, delimiter: u8) -> io::Result<()> {
+    let mut writer = csv::WriterBuilder::new().delimiter(delimiter).from_writer(w);
+    writer.write_record(&["group", "role", "size", "digest", "path", "mtime"])?;
+    for (i, group) in duplicates.directories.iter().enumerate() {
+        for member in group.members.iter() {
+            writer.write_record(&[
+                &format!("d{}", i + 1),
+                role_str(member.role),
+                &group.size.to_string(),
+                &group.digest.to_hex(),
+                &member.path.to_string_lossy(),
+                "",
+            ])?;
+        }
+    }
+    for (i, group) in duplicates.groups.iter().enumerate() {
+        for member in group.members.iter() {
+            write_csv_record(&mut writer, &(i + 1).to_string(), member, &group.digest.to_hex())?;
+        }
+    }
+    if args.find_uniques {
+        for member in duplicates.uniques.iter() {
+            write_csv_record(&mut writer, "", member, "")?;
+        }
+    }
+    writer.flush()
+}
+
+fn write_csv_record<W: Write>(writer: &mut csv::Writer<W>, group: &str, member: &Member, digest: &str) -> io::Result<()> {
+    let role = role_str(member.role);
+    let mtime = member.mtime
+    .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+    .map(|d| crate::mtime::format_time(d.as_secs()))
+    .unwrap_or_default();
+    writer.write_record(&[group, role, &member.size.to_string(), digest, &member.path.to_string_lossy(), &mtime])?;
+    Ok(())
+}
+
+fn role_str(role: Role) -> &'static str {
+    match role {
+        Role::Original => "original",
+        Role::Clone => "clone",
+        Role::Unique => "unique",
+    }
+}
+
+/// Write the file groups like `fdupes`: each group's paths one per line,
+/// original first, with a blank line after each group.
+///
+/// Each path is written as its raw bytes, like `write_path`.
+pub fn write_fdupes<W: Write>(duplicates: &Duplicates, w: &mut W) -> io::Result<()> {
+    for group in duplicates.groups.iter() {
+        for member in group.members.iter() {
+            w.write_all(member.path.as_os_str().as_bytes())?;
+            writeln!(w)?;
+        }
+        writeln!(w)?;
+    }
+    Ok(())
+}
+
+/// Write the file groups like `jdupes -j`: one JSON document with match sets.
+pub fn write_jdupes_json<W: Write>(duplicates: &Duplicates, w: &mut W) -> io::Result<()> {
+    let match_sets: Vec<serde_json::Value> = duplicates.groups.iter().map(|group| json!({
+        "fileSize": group.size,
+        "fileList": group.members.iter().map(|member| json!({ "filePath": member.path.to_string_lossy() })).collect::<Vec<_>>(),
+    })).collect();
+    serde_json::to_writer_pretty(&mut *w, &json!({
+        "jdupesVersion": env!("CARGO_PKG_VERSION"),
+        "jdupesVersionDate": "",
+        "commandLine": std::env::args().collect::<Vec<String>>().join(" "),
+        "extensionFlags": "",
+        "matchSets": match_sets,
+    }))?;
+    writeln!(w)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::action::Action;
+
+    /// Create duplicates, where the first clone is deleted.
+    fn duplicates() -> Duplicates {
+        let mut duplicates = crate::group::duplicates();
+        duplicates.groups[0].members[1].action = Action::Delete;
+        duplicates
+    }
+
+    #[test]
+    /// Test `write_text` with the default args.
+    /// Must print each clone with its original.
+    ///
+    fn test_write_text() {
+        let mut w: Vec<u8> = Vec::new();
+        write_text(&Args::default(), &duplicates(), &mut w).unwrap();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            "Clone: \"a/alpha.txt\" \"b/alpha.txt\"\nClone: \"a/alpha.txt\" \"b/alpha_2.txt\"\nClone: \"a/bravo.txt\" \"c/bravo.txt\"\n"
+        );
+    }
+
+    #[test]
+    /// Test `write_text` with `--find-clones` and `--find-uniques`.
+    /// Must print the paths.
+    ///
+    fn test_write_text_x_find() {
+        let args = Args { find_clones: true, find_uniques: true, ..Args::default() };
+        let mut w: Vec<u8> = Vec::new();
+        write_text(&args, &duplicates(), &mut w).unwrap();
+        assert_eq!(String::from_utf8(w).unwrap(), "b/alpha.txt\nb/alpha_2.txt\nc/bravo.txt\na/charlie.txt\n");
+    }
+
+    #[test]
+    /// Test `write_text` with a duplicate directory group.
+    /// Must print the clone directory with its original, before the files.
+    ///
+    fn test_write_text_x_directories() {
+        let mut duplicates = duplicates();
+        duplicates.directories.push(DirectoryGroup {
+            digest: blake3::hash(b"tree"),
+            size: 12,
+            files: 2,
+            bytes_reclaimable: 12,
+            members: vec![
+                crate::group::DirectoryMember { path: PathBuf::from("d"), role: Role::Original },
+                crate::group::DirectoryMember { path: PathBuf::from("e"), role: Role::Clone },
+            ],
+        });
+        let mut w: Vec<u8> = Vec::new();
+        write_text(&Args::default(), &duplicates, &mut w).unwrap();
+        assert!(String::from_utf8(w).unwrap().starts_with("Clone directory: \"d\" \"e\"\nClone: \"a/alpha.txt\" \"b/alpha.txt\"\n"));
+    }
+
+    #[test]
+    /// Test `write_text` with `--find-clones` and `--print0`.
+    /// Must terminate each path with NUL.
+    ///
+    fn test_write_text_x_print0() {
+        let args = Args { find_clones: true, find_uniques: true, print0: true, ..Args::default() };
+        let mut w: Vec<u8> = Vec::new();
+        write_text(&args, &duplicates(), &mut w).unwrap();
+        assert_eq!(w, b"b/alpha.txt\0b/alpha_2.txt\0c/bravo.txt\0a/charlie.txt\0");
+    }
+
+    #[test]
+    /// Test `write_path` with a file name that is not UTF-8.
+    /// Must write the raw bytes.
+    ///
+    fn test_write_path_x_not_utf8() {
+        let path = Path::new(std::ffi::OsStr::from_bytes(b"a/\xffalpha.txt"));
+        let mut w: Vec<u8> = Vec::new();
+        write_path(&Args::default(), path, &mut w).unwrap();
+        assert_eq!(w, b"a/\xffalpha.txt\n");
+    }
+
+    #[test]
+    /// Test `write_ndjson` with two groups.
+    /// Must print one line per group with the group fields, then one line with the summary.
+    ///
+    fn test_write_ndjson() {
+        let summary = Summary { duplicate_groups: 1, ..Summary::default() };
+        let mut w: Vec<u8> = Vec::new();
+        write_ndjson(&Args::default(), &duplicates(), &summary, &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        assert_eq!(s.lines().count(), 3);
+        let value: serde_json::Value = serde_json::from_str(s.lines().last().unwrap()).unwrap();
+        assert_eq!(value["type"], "summary");
+        assert_eq!(value["summary"]["duplicate_groups"], 1);
+        let value: serde_json::Value = serde_json::from_str(s.lines().next().unwrap()).unwrap();
+        assert_eq!(value["type"], "group");
+        assert_eq!(value["group"]["digest"], blake3::hash(b"alpha\n").to_hex().as_str());
+        assert_eq!(value["group"]["size"], 6);
+        assert_eq!(value["group"]["members"][0]["path"], "a/alpha.txt");
+        assert_eq!(value["group"]["members"][0]["role"], "original");
+        assert_eq!(value["group"]["members"][1]["inode"], 20);
+        assert_eq!(value["group"]["members"][1]["mtime"], 1000);
+        assert_eq!(value["group"]["members"][1]["role"], "clone");
+        assert_eq!(value["group"]["members"][1]["action"], "delete");
+    }
+
+    #[test]
+    /// Test `write_csv` with a path that contains a comma, a tab, and a newline.
+    /// Must quote the path.
+    ///
+    fn test_write_csv_x_quoting() {
+        let mut duplicates = duplicates();
+        duplicates.groups.truncate(1);
+        duplicates.groups[0].members.truncate(2);
+        duplicates.groups[0].members[1].path = PathBuf::from("b/alpha, \"one\"\t\n.txt");
+        let mut w: Vec<u8> = Vec::new();
+        write_csv(&Args::default(), &duplicates, &mut w, b',').unwrap();
+        let digest = blake3::hash(b"alpha\n").to_hex();
+        assert_eq!(
+            String::from_utf8(w).unwrap(),
+            format!(
+                "group,role,size,digest,path,mtime\n1,original,6,{},a/alpha.txt,1970-01-01T00:16:40\n1,clone,6,{},\"b/alpha, \"\"one\"\"\t\n.txt\",1970-01-01T00:16:40\n",
+                digest, digest
+            )
+        );
+    }
+
+    #[test]
+    /// Test `write_csv` as TSV, with a path that contains a tab.
+    /// Must quote the path.
+    ///
+    fn test_write_csv_x_tsv() {
+        let mut duplicates = duplicates();
+        duplicates.groups[0].members[1].path = PathBuf::from("b/alpha\t2.txt");
+        let mut w: Vec<u8> = Vec::new();
+        write_csv(&Args::default(), &duplicates, &mut w, b'\t').unwrap();
+        let s = String::from_utf8(w).unwrap();
+        assert_eq!(s.lines().next().unwrap(), "group\trole\tsize\tdigest\tpath\tmtime");
+        assert!(s.contains("\t\"b/alpha\t2.txt\"\t"));
+    }
+
+    #[test]
+    /// Test `write_fdupes` with two groups.
+    /// Must print the paths of each group then a blank line.
+    ///
+    fn test_write_fdupes() {
+        let mut w: Vec<u8> = Vec::new();
+        write_fdupes(&duplicates(), &mut w).unwrap();
+        assert_eq!(String::from_utf8(w).unwrap(), "a/alpha.txt\nb/alpha.txt\nb/alpha_2.txt\n\na/bravo.txt\nc/bravo.txt\n\n");
+    }
+
+    #[test]
+    /// Test `write_fdupes` with a directory group, and a path that is not UTF-8.
+    /// Must leave out the directory group, and print the raw bytes of the path.
+    ///
+    fn test_write_fdupes_x_directories() {
+        use std::os::unix::ffi::OsStrExt;
+        let mut duplicates = duplicates();
+        duplicates.groups[0].members[1].path = PathBuf::from(std::ffi::OsStr::from_bytes(b"b/\xff.txt"));
+        duplicates.directories.push(DirectoryGroup {
+            digest: blake3::hash(b"x"),
+            size: 6,
+            files: 1,
+            bytes_reclaimable: 6,
+            members: vec![],
+        });
+        let mut w: Vec<u8> = Vec::new();
+        write_fdupes(&duplicates, &mut w).unwrap();
+        assert_eq!(w, b"a/alpha.txt\nb/\xff.txt\nb/alpha_2.txt\n\na/bravo.txt\nc/bravo.txt\n\n".to_vec());
+    }
+
+    #[test]
+    /// Test `write_jdupes_json` with two groups.
+    /// Must print one match set per group.
+    ///
+    fn test_write_jdupes_json() {
+        let mut w: Vec<u8> = Vec::new();
+        write_jdupes_json(&duplicates(), &mut w).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&w).unwrap();
+        assert_eq!(value["matchSets"][0]["fileSize"], 6);
+        assert_eq!(value["matchSets"][0]["fileList"][0]["filePath"], "a/alpha.txt");
+        assert_eq!(value["matchSets"][0]["fileList"][1]["filePath"], "b/alpha.txt");
+        assert_eq!(value["matchSets"][1]["fileSize"], 8);
+    }
+
+    #[test]
+    /// Test `write_json` with `--find-uniques`.
+    /// Must print the groups and the uniques.
+    ///
+    fn test_write_json_x_find_uniques() {
+        let args = Args { find_uniques: true, ..Args::default() };
+        let mut w: Vec<u8> = Vec::new();
+        write_json(&args, &duplicates(), &Summary::default(), &mut w).unwrap();
+        let value: serde_json::Value = serde_json::from_slice(&w).unwrap();
+        assert_eq!(value["groups"].as_array().unwrap().len(), 2);
+        assert_eq!(value["uniques"][0]["path"], "a/charlie.txt");
+        assert_eq!(value["uniques"][0]["role"], "unique");
+        assert_eq!(value["summary"]["files_scanned"], 0);
+    }
+
+}
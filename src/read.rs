@@ -0,0 +1,147 @@
+//! File reading for comparing and hashing.
+//!
+//! Large sequential reads keep a disk busy, so we read with a large
+//! buffer that we fill fully, even when `read` returns short counts,
+//! and we hint to the kernel that reads are sequential.
+//!
+//! Optionally, we memory-map files, which avoids copying into buffers.
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+use memmap2::Mmap;
+
+/// Default buffer size for reading files
+pub const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// How to read files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReadOptions {
+    pub buffer_size: usize,
+    pub mmap: bool,
+}
+
+impl Default for ReadOptions {
+    fn default() -> Self {
+        ReadOptions { buffer_size: DEFAULT_BUFFER_SIZE, mmap: false }
+    }
+}
+
+/// Parse a size such as `8192`, `64K`, `1M`, or `1G` into bytes.
+pub fn parse_size(s: &str) -> io::Result<usize> {
+    let s = s.trim();
+    let (number, multiplier) = match s.chars().last() {
+        Some('K') | Some('k') => (&s[..s.len() - 1], 1024),
+        Some('M') | Some('m') => (&s[..s.len() - 1], 1024 * 1024),
+        Some('G') | Some('g') => (&s[..s.len() - 1], 1024 * 1024 * 1024),
+        _ => (s, 1),
+    };
+    number
+    .parse::<usize>()
+    .ok()
+    .and_then(|n| n.checked_mul(multiplier))
+    .filter(|n| *n > 0)
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("size:{}", s)))
+}
+
+/// Open a file for reading, with a hint that reads are sequential.
+pub fn open_sequential<T: AsRef<Path>>(path: T) -> io::Result<File> {
+    let file = File::open(path)?;
+    advise_sequential(&file);
+    Ok(file)
+}
+
+/// Hint to the kernel that reads are sequential, so it reads ahead more.
+///
+/// This is only a hint, so we ignore any error.
+#[cfg(target_os = "linux")]
+pub fn advise_sequential(file: &File) {
+    use std::os::unix::io::AsRawFd;
+    unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_SEQUENTIAL); }
+}
+
+/// Hint to the kernel that reads are sequential; unsupported on this platform.
+#[cfg(not(target_os = "linux"))]
+pub fn advise_sequential(_file: &File) {
+}
+
+/// Read until the buffer is full or the reader is at end of file.
+///
+/// Return the count of bytes read, which is less than the buffer length
+/// only at end of file. A plain `read` can return a short count anytime.
+pub fn read_full<R: Read>(reader: &mut R, buffer: &mut [u8]) -> io::Result<usize> {
+    let mut n = 0;
+    while n < buffer.len() {
+        match reader.read(&mut buffer[n..]) {
+            Ok(0) => break,
+            Ok(k) => n += k,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(n)
+}
+
+/// Memory-map a file for reading, or None if the file is empty,
+/// because an empty file can't be mapped.
+pub fn mmap(file: &File) -> io::Result<Option<Mmap>> {
+    match file.metadata()?.len() {
+        0 => Ok(None),
+        _ => Ok(Some(unsafe { Mmap::map(file)? })),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A reader that returns at most `n` bytes per read.
+    struct ShortReader<'a> {
+        bytes: &'a [u8],
+        n: usize,
+    }
+
+    impl<'a> Read for ShortReader<'a> {
+        fn read(&mut self, buffer: &mut [u8]) -> io::Result<usize> {
+            let k = std::cmp::min(std::cmp::min(self.n, buffer.len()), self.bytes.len());
+            buffer[..k].copy_from_slice(&self.bytes[..k]);
+            self.bytes = &self.bytes[k..];
+            Ok(k)
+        }
+    }
+
+    #[test]
+    /// Test `read_full` with a reader that returns short counts.
+    /// Must fill the buffer.
+    ///
+    fn test_read_full_x_short_reads() {
+        let mut reader = ShortReader { bytes: b"alphabravo", n: 3 };
+        let mut buffer = [0; 8];
+        assert_eq!(read_full(&mut reader, &mut buffer).unwrap(), 8);
+        assert_eq!(&buffer, b"alphabra");
+        assert_eq!(read_full(&mut reader, &mut buffer).unwrap(), 2);
+        assert_eq!(&buffer[..2], b"vo");
+        assert_eq!(read_full(&mut reader, &mut buffer).unwrap(), 0);
+    }
+
+    #[test]
+    /// Test `parse_size` with units.
+    ///
+    fn test_parse_size() {
+        assert_eq!(parse_size("8192").unwrap(), 8192);
+        assert_eq!(parse_size("64K").unwrap(), 65536);
+        assert_eq!(parse_size("1M").unwrap(), 1048576);
+        assert_eq!(parse_size("1g").unwrap(), 1073741824);
+    }
+
+    #[test]
+    /// Test `parse_size` with invalid input.
+    /// Must err.
+    ///
+    fn test_parse_size_x_invalid() {
+        assert_eq!(parse_size("").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_size("0").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_size("1X").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+}
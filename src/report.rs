@@ -0,0 +1,178 @@
+//! Reports for people who don't use a terminal.
+//!
+//! The HTML report is one static page, with its styles and script inline,
+//! so it can be sent as one file. It lists duplicate groups sorted by bytes
+//! reclaimable, each collapsible, then totals per directory, with a filter
+//! box that hides groups whose paths don't contain the filter text.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+use crate::FileLen;
+use crate::args::Args;
+use crate::directory;
+use crate::group::{DuplicateGroup, Duplicates, Role};
+
+/// Report kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Report {
+    Html,
+}
+
+impl FromStr for Report {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "html" => Ok(Report::Html),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("report:{}", s))),
+        }
+    }
+}
+
+/// Write a report of the duplicates to a file.
+pub fn write_report<T: AsRef<Path>>(args: &Args, report: Report, duplicates: &Duplicates, path: T) -> io::Result<()> {
+    let mut w = io::BufWriter::new(fs::File::create(path)?);
+    match report {
+        Report::Html => write_html(args, duplicates, &mut w)?,
+    }
+    w.flush()
+}
+
+/// Write the duplicates as one self-contained HTML page.
+pub fn write_html<W: Write>(args: &Args, duplicates: &Duplicates, w: &mut W) -> io::Result<()> {
+    let mut groups: Vec<&DuplicateGroup> = duplicates.groups.iter().collect();
+    groups.sort_by_key(|group| std::cmp::Reverse(group.bytes_reclaimable));
+    let bytes_reclaimable: FileLen = groups.iter().map(|group| group.bytes_reclaimable).sum();
+    writeln!(w, "<!DOCTYPE html>")?;
+    writeln!(w, "<html lang=\"en\">")?;
+    writeln!(w, "<head>")?;
+    writeln!(w, "<meta charset=\"utf-8\">")?;
+    writeln!(w, "<title>Duplicate files</title>")?;
+    writeln!(w, "<style>{}</style>", STYLE)?;
+    writeln!(w, "</head>")?;
+    writeln!(w, "<body>")?;
+    writeln!(w, "<h1>Duplicate files</h1>")?;
+    writeln!(w, "<p>{} groups, {} reclaimable.</p>", groups.len(), format_bytes(bytes_reclaimable))?;
+    writeln!(w, "<p><input id=\"filter\" type=\"search\" placeholder=\"Filter by path\" oninput=\"filter(this.value)\"></p>")?;
+    writeln!(w, "<h2>Groups</h2>")?;
+    for group in groups {
+        writeln!(w, "<details class=\"group\">")?;
+        writeln!(
+            w,
+            "<summary>{} reclaimable: {} files of {} each <code>{}</code></summary>",
+            format_bytes(group.bytes_reclaimable),
+            group.members.len(),
+            format_bytes(group.size),
+            &group.digest.to_hex()[..16],
+        )?;
+        writeln!(w, "<table>")?;
+        for member in group.members.iter() {
+            let role = match member.role {
+                Role::Original => "original",
+                Role::Clone => "clone",
+                Role::Unique => "unique",
+            };
+            writeln!(w, "<tr class=\"{}\"><td>{}</td><td class=\"path\">{}</td></tr>", role, role, escape(&member.path.to_string_lossy()))?;
+        }
+        writeln!(w, "</table>")?;
+        writeln!(w, "</details>")?;
+    }
+    writeln!(w, "<h2>Directories</h2>")?;
+    writeln!(w, "<table class=\"directories\">")?;
+    writeln!(w, "<tr><th>Directory</th><th>Clones</th><th>Reclaimable</th></tr>")?;
+    for (dir, total) in directory::totals(args, duplicates, false) {
+        writeln!(
+            w,
+            "<tr class=\"directory\"><td class=\"path\">{}</td><td>{}</td><td>{}</td></tr>",
+            escape(&dir.to_string_lossy()),
+            total.clones,
+            format_bytes(total.bytes_reclaimable),
+        )?;
+    }
+    writeln!(w, "</table>")?;
+    writeln!(w, "<script>{}</script>", SCRIPT)?;
+    writeln!(w, "</body>")?;
+    writeln!(w, "</html>")
+}
+
+const STYLE: &str = "body{font-family:sans-serif;margin:2em}\
+summary{cursor:pointer;padding:0.2em 0}\
+code,.path{font-family:monospace}\
+table{border-collapse:collapse;margin:0.5em 0 1em 1.5em}\
+td,th{padding:0.1em 0.8em;text-align:left}\
+tr.original{font-weight:bold}\
+.hidden{display:none}";
+
+const SCRIPT: &str = "function filter(text){\
+text=text.toLowerCase();\
+document.querySelectorAll('.group,.directory').forEach(function(e){\
+var paths=Array.prototype.map.call(e.querySelectorAll('.path'),function(p){return p.textContent.toLowerCase()});\
+e.classList.toggle('hidden',!paths.some(function(p){return p.indexOf(text)>=0}));\
+});\
+}";
+
+/// Escape text for HTML.
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Format bytes for people, such as `512 B` or `1.5 MiB`.
+pub fn format_bytes(n: FileLen) -> String {
+    const UNITS: [&str; 5] = ["KiB", "MiB", "GiB", "TiB", "PiB"];
+    if n < 1024 {
+        return format!("{} B", n)
+    }
+    let mut x = n as f64 / 1024.0;
+    let mut i = 0;
+    while x >= 1024.0 && i + 1 < UNITS.len() {
+        x /= 1024.0;
+        i += 1;
+    }
+    format!("{:.1} {}", x, UNITS[i])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+    use crate::group::duplicates;
+
+    #[test]
+    /// Test `write_html`.
+    /// Must sort groups by bytes reclaimable, and escape paths.
+    ///
+    fn test_write_html() {
+        let mut duplicates = duplicates();
+        duplicates.groups[1].members[1].path = PathBuf::from("c/<bravo>.txt");
+        let mut w: Vec<u8> = Vec::new();
+        write_html(&Args::default(), &duplicates, &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        assert!(s.starts_with("<!DOCTYPE html>\n"));
+        assert!(s.find("c/&lt;bravo&gt;.txt").unwrap() < s.find("b/alpha.txt").unwrap());
+        assert!(!s.contains("<bravo>"));
+        assert!(s.contains("<p>2 groups, 14 B reclaimable.</p>"));
+    }
+
+    #[test]
+    /// Test `format_bytes` with units.
+    ///
+    fn test_format_bytes() {
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(1536), "1.5 KiB");
+        assert_eq!(format_bytes(1024 * 1024), "1.0 MiB");
+    }
+
+}
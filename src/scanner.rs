@@ -0,0 +1,242 @@
+//! Scanner, the library API to scan roots and detect duplicates.
+//!
+//! A scanner is a builder over the same args that the command line sets,
+//! so the library and the binary scan, filter, and hash alike. Hashing
+//! runs on the current rayon thread pool, so to limit threads, call
+//! `scan` within `rayon::ThreadPool::install`.
+//!
+//! To stream groups, `groups` walks every root first, because a size
+//! bucket can grow until the walk ends, so memory holds every scanned path.
+//! Then it hashes and resolves one bucket at a time, in size order, only
+//! when the iterator is advanced, so the first group arrives before the
+//! other buckets are hashed. Each bucket is dropped when it's resolved,
+//! and groups aren't collected.
+
+use std::path::PathBuf;
+use std::time::SystemTime;
+use crate::{FileLen, Tracker, detect_duplicates, detect_duplicates_of_bucket, on_paths};
+use crate::args::Args;
+use crate::compare::Compare;
+use crate::device::ReadOrder;
+use crate::group::{DuplicateGroup, Duplicates};
+
+/// Builder to scan roots and detect duplicates.
+#[derive(Default, Debug)]
+pub struct Scanner {
+    args: Args,
+}
+
+impl Scanner {
+
+    /// Create a scanner with no roots, and the default filters and hash options.
+    pub fn new() -> Self {
+        Scanner::default()
+    }
+
+    /// Add a root to scan. The earliest root that holds a duplicate holds its original.
+    pub fn root<T: Into<PathBuf>>(mut self, path: T) -> Self {
+        self.args.paths.push(path.into());
+        self
+    }
+
+    /// Add a reference root to scan, whose files are only ever originals.
+    pub fn reference<T: Into<PathBuf>>(mut self, path: T) -> Self {
+        self.args.reference.push(path.into());
+        self
+    }
+
+    /// Follow symlinks when walking.
+    pub fn follow_symlinks(mut self, follow_symlinks: bool) -> Self {
+        self.args.follow_symlinks = follow_symlinks;
+        self
+    }
+
+    /// Stay on the file system of each root.
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.args.one_file_system = one_file_system;
+        self
+    }
+
+    /// Skip entries that are fewer than this many levels below their root.
+    pub fn min_depth(mut self, min_depth: usize) -> Self {
+        self.args.min_depth = Some(min_depth);
+        self
+    }
+
+    /// Skip entries that are more than this many levels below their root.
+    pub fn max_depth(mut self, max_depth: usize) -> Self {
+        self.args.max_depth = Some(max_depth);
+        self
+    }
+
+    /// Skip hidden files and directories, i.e. whose names start with a dot.
+    pub fn skip_hidden(mut self, skip_hidden: bool) -> Self {
+        self.args.skip_hidden = skip_hidden;
+        self
+    }
+
+    /// Skip files that are modified at or before this time.
+    pub fn newer_than(mut self, time: SystemTime) -> Self {
+        self.args.newer_than = Some(time);
+        self
+    }
+
+    /// Skip files that are modified at or after this time.
+    pub fn older_than(mut self, time: SystemTime) -> Self {
+        self.args.older_than = Some(time);
+        self
+    }
+
+    /// Treat existing hardlinks as already deduplicated, i.e. never clones of each other.
+    pub fn hardlinks_deduplicated(mut self, hardlinks_deduplicated: bool) -> Self {
+        self.args.hardlinks_deduplicated = hardlinks_deduplicated;
+        self
+    }
+
+    /// Compare by digest, or in lockstep.
+    pub fn compare(mut self, compare: Compare) -> Self {
+        self.args.compare = compare;
+        self
+    }
+
+    /// Read at most this many files at once per device; 0 means 1 for a spinning disk, and no limit otherwise.
+    pub fn jobs_per_device(mut self, jobs_per_device: usize) -> Self {
+        self.args.jobs_per_device = jobs_per_device;
+        self
+    }
+
+    /// Order reads on rotational disks.
+    pub fn read_order(mut self, read_order: ReadOrder) -> Self {
+        self.args.read_order = read_order;
+        self
+    }
+
+    /// Read files with this buffer size.
+    pub fn buffer_size(mut self, buffer_size: usize) -> Self {
+        self.args.read_options.buffer_size = buffer_size;
+        self
+    }
+
+    /// Read files via memory maps.
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.args.read_options.mmap = mmap;
+        self
+    }
+
+    /// Scan the reference roots and the roots, then detect duplicates.
+    pub fn scan(&self) -> Duplicates {
+        let mut tracker = Tracker::new();
+        on_paths(&self.args, &mut tracker, &self.args.reference);
+        on_paths(&self.args, &mut tracker, &self.args.paths);
+        detect_duplicates(&self.args, &tracker)
+    }
+
+    /// Walk the reference roots and the roots, then iterate the duplicate
+    /// groups, which are hashed and detected one size bucket at a time.
+    pub fn groups(&self) -> Groups<'_> {
+        let mut tracker = Tracker::new();
+        on_paths(&self.args, &mut tracker, &self.args.reference);
+        on_paths(&self.args, &mut tracker, &self.args.paths);
+        let mut lens: Vec<FileLen> = tracker.map.keys().cloned().collect();
+        lens.sort();
+        Groups {
+            args: &self.args,
+            tracker: tracker,
+            lens: lens.into_iter(),
+            pending: Vec::new().into_iter(),
+        }
+    }
+
+}
+
+/// Iterator of duplicate groups, in size order, which resolves each size
+/// bucket when its groups are needed.
+pub struct Groups<'a> {
+    args: &'a Args,
+    tracker: Tracker,
+    lens: std::vec::IntoIter<FileLen>,
+    pending: std::vec::IntoIter<DuplicateGroup>,
+}
+
+impl Iterator for Groups<'_> {
+    type Item = DuplicateGroup;
+
+    fn next(&mut self) -> Option<DuplicateGroup> {
+        loop {
+            if let Some(group) = self.pending.next() { return Some(group) }
+            let len = self.lens.next()?;
+            if let Some(paths) = self.tracker.map.remove(&len) {
+                self.pending = detect_duplicates_of_bucket(self.args, &paths).groups.into_iter();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::group::Role;
+
+    #[test]
+    /// Test `Scanner` via these files:
+    ///
+    /// * `a/alpha.txt` which contains `alpha`
+    /// * `a/bravo.txt` which contains `bravo`
+    /// * `b/alpha.txt` which contains `alpha`
+    /// * `b/alpha_2.txt` which contains `alpha`
+    ///
+    /// Must detect the same group as the command line, with `b` as the reference root.
+    ///
+    fn test_scanner() {
+        let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates"].iter().collect();
+        let duplicates = Scanner::new().root(dir.join("a")).reference(dir.join("b")).scan();
+        assert_eq!(duplicates.groups.len(), 1);
+        assert_eq!(duplicates.groups[0].original(), Some(&dir.join("b").join("alpha.txt")));
+        assert_eq!(duplicates.groups[0].members.iter().filter(|member| member.role == Role::Clone).count(), 1);
+        assert_eq!(duplicates.uniques.len(), 1);
+    }
+
+    #[test]
+    /// Test `Scanner::groups` via these files:
+    ///
+    /// * `a/alpha.txt` which contains `alpha`
+    /// * `a/bravo.txt` which contains `bravo`
+    /// * `b/alpha.txt` which contains `alpha`
+    /// * `b/alpha_2.txt` which contains `alpha`
+    ///
+    /// Must yield the same groups as `Scanner::scan`.
+    ///
+    fn test_scanner_groups() {
+        let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates"].iter().collect();
+        let scanner = Scanner::new().root(dir.join("a")).root(dir.join("b"));
+        let groups: Vec<DuplicateGroup> = scanner.groups().collect();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups, scanner.scan().groups);
+    }
+
+    #[test]
+    /// Test `Scanner::groups` via a temp dir with these files:
+    ///
+    /// * `alpha.txt` which contains `alpha`
+    /// * `alpha_2.txt` which contains `alpha`
+    /// * `charlie.txt` which contains `charlie`
+    /// * `charlie_2.txt` which contains `charlie`
+    ///
+    /// Must yield the `alpha` group before hashing the `charlie` bucket,
+    /// so `charlie_2.txt`, which is removed meanwhile, is in no group.
+    ///
+    fn test_scanner_groups_x_lazy() {
+        let dir = std::env::temp_dir().join("deduplicate-files-test-scanner-lazy");
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for (path, content) in &[("alpha.txt", "alpha\n"), ("alpha_2.txt", "alpha\n"), ("charlie.txt", "charlie\n"), ("charlie_2.txt", "charlie\n")] {
+            std::fs::write(dir.join(path), content).unwrap();
+        }
+        let scanner = Scanner::new().root(&dir);
+        let mut groups = scanner.groups();
+        assert_eq!(groups.next().unwrap().original(), Some(&dir.join("alpha.txt")));
+        std::fs::remove_file(dir.join("charlie_2.txt")).unwrap();
+        assert_eq!(groups.next(), None);
+    }
+
+}
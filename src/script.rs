@@ -0,0 +1,252 @@
+//! Shell scripts of actions.
+//!
+//! Instead of acting on clones, we can write a POSIX shell script with one
+//! command per clone, so an admin can review it, edit it, then run it.
+//! Each group starts with comments that show its digest and its original.
+//!
+//! Each path is single-quoted, so any file name is safe, even one with
+//! spaces, quotes, newlines, or bytes that are not UTF-8. Comments show
+//! paths escaped, so a newline in a name can't end a comment.
+//!
+//! Recycling is a shell function that names and records each file in the
+//! trash like `--recycle` does. Shredding needs `shred` from GNU coreutils,
+//! so the script checks for it first.
+
+use std::fs;
+use std::io::{self, Write};
+use std::os::unix::ffi::OsStrExt;
+use std::path::Path;
+use crate::action::{self, Action};
+use crate::group::{Duplicates, Member, Role};
+
+/// Quote bytes for a POSIX shell, by wrapping them in single quotes,
+/// and by writing each single quote as `'\''`.
+pub fn quote(bytes: &[u8]) -> Vec<u8> {
+    let mut quoted: Vec<u8> = Vec::with_capacity(bytes.len() + 2);
+    quoted.push(b'\'');
+    for byte in bytes {
+        match byte {
+            b'\'' => quoted.extend_from_slice(b"'\\''"),
+            _ => quoted.push(*byte),
+        }
+    }
+    quoted.push(b'\'');
+    quoted
+}
+
+/// Quote a path for a POSIX shell.
+pub fn quote_path(path: &Path) -> Vec<u8> {
+    quote(path.as_os_str().as_bytes())
+}
+
+/// Write a script of the action of each clone.
+///
+/// The script changes to the current directory first, so relative paths
+/// mean what they meant here, then stops at the first failing command.
+pub fn write_script<W: Write>(duplicates: &Duplicates, w: &mut W) -> io::Result<()> {
+    writeln!(w, "#!/bin/sh")?;
+    writeln!(w, "# Deduplicate files. Review this script, then run it.")?;
+    writeln!(w, "set -eu")?;
+    w.write_all(b"cd -- ")?;
+    w.write_all(&quote_path(&std::env::current_dir()?))?;
+    writeln!(w)?;
+    let actions: Vec<Action> = duplicates.groups.iter().flat_map(|group| group.clones().map(|member| member.action)).collect();
+    if actions.contains(&Action::Shred) {
+        w.write_all(SHRED)?;
+    }
+    if actions.contains(&Action::Recycle) {
+        w.write_all(RECYCLE)?;
+    }
+    for group in duplicates.groups.iter() {
+        let original = match group.members.iter().find(|member| member.role == Role::Original) {
+            Some(member) => member,
+            None => continue,
+        };
+        let clones: Vec<&Member> = group.clones().filter(|member| member.action != Action::None).collect();
+        if clones.is_empty() { continue }
+        writeln!(w)?;
+        writeln!(w, "# digest: {} size: {}", group.digest.to_hex(), group.size)?;
+        writeln!(w, "# original: {:?}", original.path)?;
+        for clone in clones {
+            write_command(original, clone, w)?;
+        }
+    }
+    Ok(())
+}
+
+/// Check for `shred`, which is in GNU coreutils, yet not in POSIX.
+const SHRED: &[u8] = br#"command -v shred >/dev/null || { echo "shred: not found; install GNU coreutils" >&2; exit 1; }
+"#;
+
+/// Move a file to the trash, per the freedesktop.org trash spec, like
+/// `action::recycle`: take the first free name of `NAME`, `NAME.1`, and so
+/// on, then write its `.trashinfo` file, then move it without overwriting.
+const RECYCLE: &[u8] = br#"TRASH="${XDG_DATA_HOME:-$HOME/.local/share}/Trash"
+recycle() {
+    name=${1##*/}
+    case "$1" in */*) dir=${1%/*} ;; *) dir=. ;; esac
+    dir=$(cd -- "${dir:-/}" && pwd -P)
+    [ "$dir" = / ] && dir=
+    mkdir -p -- "$TRASH/files" "$TRASH/info"
+    trash_name=$name
+    i=1
+    while [ -e "$TRASH/files/$trash_name" ] || [ -e "$TRASH/info/$trash_name.trashinfo" ]; do
+        trash_name=$name.$i
+        i=$((i + 1))
+    done
+    printf '[Trash Info]
+Path=%s/%s
+DeletionDate=%s
+' "$dir" "$name" "$(date -u +%Y-%m-%dT%H:%M:%S)" > "$TRASH/info/$trash_name.trashinfo"
+    mv -n -- "$1" "$TRASH/files/$trash_name"
+}
+"#;
+
+/// Write the command of the action of one clone.
+///
+/// A link is created at a temporary path then renamed over the clone,
+/// like `Action::apply`. A clone that is already a hardlink of the original
+/// gets only a comment, because `mv` would fail on the same file, and the
+/// script would stop there.
+fn write_command<W: Write>(original: &Member, clone: &Member, w: &mut W) -> io::Result<()> {
+    let path = quote_path(&clone.path);
+    let tmp = quote_path(&action::tmp_path(&clone.path)?);
+    let mut line: Vec<u8> = Vec::new();
+    match clone.action {
+        Action::None => return Ok(()),
+        Action::Delete => {
+            line.extend_from_slice(b"rm -f -- ");
+            line.extend_from_slice(&path);
+        },
+        Action::Shred => {
+            // A hardlink to the original must not be overwritten, so only remove it.
+            let same_file = (clone.dev, clone.inode) == (original.dev, original.inode);
+            let command: &[u8] = if same_file { b"rm -f -- " } else { b"shred -u -- " };
+            line.extend_from_slice(command);
+            line.extend_from_slice(&path);
+        },
+        Action::Recycle => {
+            line.extend_from_slice(b"recycle ");
+            line.extend_from_slice(&path);
+        },
+        Action::Hardlink if (clone.dev, clone.inode) == (original.dev, original.inode) => {
+            return writeln!(w, "# already hardlinked: {:?}", clone.path)
+        },
+        Action::Hardlink | Action::Symlink => {
+            if clone.action == Action::Hardlink {
+                line.extend_from_slice(b"ln -- ");
+                line.extend_from_slice(&quote_path(&original.path));
+            } else {
+                line.extend_from_slice(b"ln -s -- ");
+                line.extend_from_slice(&quote_path(&fs::canonicalize(&original.path).unwrap_or_else(|_| original.path.clone())));
+            }
+            line.push(b' ');
+            line.extend_from_slice(&tmp);
+            line.extend_from_slice(b" && mv -f -- ");
+            line.extend_from_slice(&tmp);
+            line.push(b' ');
+            line.extend_from_slice(&path);
+        },
+    }
+    line.push(b'\n');
+    w.write_all(&line)
+}
+
+/// Write a script of the action of each clone to a file, and make it executable.
+pub fn emit_script<T: AsRef<Path>>(duplicates: &Duplicates, path: T) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mut w = io::BufWriter::new(fs::File::create(&path)?);
+    write_script(duplicates, &mut w)?;
+    w.flush()?;
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// Create duplicates, where each clone has an action, and one clone's name has a quote.
+    fn duplicates(action: Action) -> Duplicates {
+        let mut duplicates = crate::group::duplicates();
+        duplicates.groups[0].members[1].path = PathBuf::from("b/it's alpha.txt");
+        for group in duplicates.groups.iter_mut() {
+            group.members.iter_mut().filter(|member| member.role == Role::Clone).for_each(|member| member.action = action);
+        }
+        duplicates
+    }
+
+    #[test]
+    /// Test `quote` with a single quote.
+    ///
+    fn test_quote() {
+        assert_eq!(quote(b"alpha"), b"'alpha'");
+        assert_eq!(quote(b"it's"), b"'it'\\''s'");
+        assert_eq!(quote(b"a\nb"), b"'a\nb'");
+    }
+
+    #[test]
+    /// Test `write_script` with `Action::Delete`.
+    /// Must write comments and one quoted `rm`.
+    ///
+    fn test_write_script_x_delete() {
+        let mut w: Vec<u8> = Vec::new();
+        write_script(&duplicates(Action::Delete), &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        assert!(s.starts_with("#!/bin/sh\n"));
+        assert!(s.contains(&format!("# digest: {} size: 6\n", blake3::hash(b"alpha\n").to_hex())));
+        assert!(s.contains("# original: \"a/alpha.txt\"\n"));
+        assert!(s.contains("rm -f -- 'b/it'\\''s alpha.txt'\n"));
+    }
+
+    #[test]
+    /// Test `write_script` with `Action::Hardlink`.
+    /// Must write a quoted `ln` to a temporary path then a `mv` over the clone,
+    /// and only a comment for a clone that is already a hardlink of the original.
+    ///
+    fn test_write_script_x_hardlink() {
+        let mut w: Vec<u8> = Vec::new();
+        write_script(&duplicates(Action::Hardlink), &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        assert!(s.contains("ln -- 'a/alpha.txt' 'b/.it'\\''s alpha.txt.deduplicate-tmp' && mv -f -- 'b/.it'\\''s alpha.txt.deduplicate-tmp' 'b/it'\\''s alpha.txt'\n"));
+        assert!(s.contains("# already hardlinked: \"b/alpha_2.txt\"\n"));
+        assert!(!s.contains("'b/alpha_2.txt'"));
+    }
+
+    #[test]
+    /// Test `write_script` with `Action::Recycle`.
+    /// Must define the `recycle` function, then call it with the quoted clone.
+    ///
+    fn test_write_script_x_recycle() {
+        let mut w: Vec<u8> = Vec::new();
+        write_script(&duplicates(Action::Recycle), &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        assert!(s.contains("recycle() {\n"));
+        assert!(s.contains("mv -n -- "));
+        assert!(s.contains("recycle 'b/it'\\''s alpha.txt'\n"));
+        assert!(!s.contains("command -v shred"));
+    }
+
+    #[test]
+    /// Test `write_script` with `Action::Shred`.
+    /// Must check for `shred` first.
+    ///
+    fn test_write_script_x_shred() {
+        let mut w: Vec<u8> = Vec::new();
+        write_script(&duplicates(Action::Shred), &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        assert!(s.contains("command -v shred >/dev/null || "));
+        assert!(s.contains("shred -u -- 'b/it'\\''s alpha.txt'\n"));
+    }
+
+    #[test]
+    /// Test `write_script` with `Action::None`.
+    /// Must write no group.
+    ///
+    fn test_write_script_x_none() {
+        let mut w: Vec<u8> = Vec::new();
+        write_script(&duplicates(Action::None), &mut w).unwrap();
+        assert!(!String::from_utf8(w).unwrap().contains("# digest"));
+    }
+
+}
@@ -0,0 +1,152 @@
+//! Subset directories.
+//!
+//! A directory is a subset of another directory when every file in it has
+//! a content-identical file somewhere in the other, regardless of names or
+//! layout. For partial containment, the overlap is the percent of its
+//! files whose content is in the other.
+//!
+//! For each directory, we find the other directories with the most of its
+//! files, skip the ancestors of those, because an ancestor contains at
+//! least as much, and report them if the overlap is at least the minimum.
+//! A directory within a directory that is fully a subset is implied, so we
+//! skip it. A directory is never compared with its own ancestors or
+//! descendants.
+
+use std::collections::{HashMap, HashSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use serde::Serialize;
+use crate::Tracker;
+use crate::args::Args;
+use crate::digest::Digest;
+use crate::group::{Duplicates, serialize_path};
+use crate::tree::is_within_paths;
+
+/// A directory whose files are fully or partly in another directory.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Subset {
+    #[serde(serialize_with = "serialize_path")]
+    pub path: PathBuf,
+    #[serde(serialize_with = "serialize_path")]
+    pub superset: PathBuf,
+    pub files: usize,
+    pub files_contained: usize,
+    /// Percent of the files whose content is in the superset.
+    pub overlap: f64,
+}
+
+/// Parse a percent from 0 to 100, such as `50` or `99.5`.
+pub fn parse_percent(s: &str) -> io::Result<f64> {
+    s
+    .trim()
+    .parse::<f64>()
+    .ok()
+    .filter(|x| (0.0..=100.0).contains(x))
+    .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("percent:{}", s)))
+}
+
+/// Detect subset directories, sorted by overlap, most first, then by path.
+pub fn detect_subsets(args: &Args, tracker: &Tracker, duplicates: &Duplicates) -> Vec<Subset> {
+    let digests: HashMap<&Path, Digest> = duplicates.groups
+    .iter()
+    .flat_map(|group| group.members.iter().map(move |member| (member.path.as_path(), group.digest)))
+    .collect();
+    let mut files: HashMap<&Path, usize> = HashMap::new();
+    let mut contents: HashMap<&Path, HashMap<Digest, usize>> = HashMap::new();
+    let mut index: HashMap<Digest, HashSet<&Path>> = HashMap::new();
+    for path in tracker.map.values().flatten() {
+        let digest = digests.get(path.as_path());
+        for dir in path.ancestors().skip(1).filter(|dir| is_within_paths(args, dir)) {
+            *files.entry(dir).or_insert(0) += 1;
+            if let Some(digest) = digest {
+                *contents.entry(dir).or_default().entry(*digest).or_insert(0) += 1;
+                index.entry(*digest).or_default().insert(dir);
+            }
+        }
+    }
+    let min_overlap = args.subsets_min_overlap;
+    let mut subsets: Vec<Subset> = Vec::new();
+    for (dir, dir_contents) in contents.iter() {
+        let mut counts: HashMap<&Path, usize> = HashMap::new();
+        for (digest, n) in dir_contents.iter() {
+            for other in index[digest].iter() {
+                if !other.starts_with(dir) && !dir.starts_with(other) {
+                    *counts.entry(other).or_insert(0) += n;
+                }
+            }
+        }
+        let best = match counts.values().max() {
+            Some(best) => *best,
+            None => continue,
+        };
+        let overlap = 100.0 * best as f64 / files[dir] as f64;
+        if overlap < min_overlap { continue }
+        let supersets: Vec<&Path> = counts.iter().filter(|(_, n)| **n == best).map(|(other, _)| *other).collect();
+        for superset in supersets.iter() {
+            if supersets.iter().any(|other| other != superset && other.starts_with(superset)) { continue }
+            subsets.push(Subset {
+                path: dir.to_path_buf(),
+                superset: superset.to_path_buf(),
+                files: files[dir],
+                files_contained: best,
+                overlap: overlap,
+            });
+        }
+    }
+    let full: HashSet<PathBuf> = subsets.iter().filter(|subset| subset.files_contained == subset.files).map(|subset| subset.path.clone()).collect();
+    subsets.retain(|subset| !subset.path.ancestors().skip(1).any(|ancestor| full.contains(ancestor)));
+    subsets.sort_by(|a, b| {
+        b.overlap.partial_cmp(&a.overlap).unwrap_or(std::cmp::Ordering::Equal)
+        .then_with(|| (&a.path, &a.superset).cmp(&(&b.path, &b.superset)))
+    });
+    subsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test `detect_subsets` via these files:
+    ///
+    /// * `a/alpha.txt` which contains `alpha`
+    /// * `a/x/bravo.txt` which contains `bravo`
+    /// * `b/alpha.txt` which contains `alpha`
+    /// * `b/x/bravo.txt` which contains `bravo`
+    /// * `c/alpha.txt` which contains `alpha`
+    /// * `c/charlie.txt` which contains `charlie`
+    ///
+    /// Must report `a` and `b` as subsets of each other, and skip `a/x` and `b/x`,
+    /// which are within them, and must report `c` as half in `a` and in `b`.
+    ///
+    fn test_detect_subsets() {
+        let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "tree"].iter().collect();
+        let args = Args { paths: vec![dir.clone()], subsets_min_overlap: 50.0, ..Args::default() };
+        let mut tracker = Tracker::new();
+        crate::on_paths(&args, &mut tracker, &args.paths);
+        let duplicates = crate::detect_duplicates(&args, &tracker);
+        let subsets = detect_subsets(&args, &tracker, &duplicates);
+        let found: Vec<(PathBuf, PathBuf, usize, usize)> = subsets
+        .iter()
+        .map(|subset| (subset.path.clone(), subset.superset.clone(), subset.files, subset.files_contained))
+        .collect();
+        assert_eq!(found, vec![
+            (dir.join("a"), dir.join("b"), 2, 2),
+            (dir.join("b"), dir.join("a"), 2, 2),
+            (dir.join("c"), dir.join("a"), 2, 1),
+            (dir.join("c"), dir.join("b"), 2, 1),
+        ]);
+        assert_eq!(subsets[2].overlap, 50.0);
+    }
+
+    #[test]
+    /// Test `parse_percent` with valid and invalid input.
+    ///
+    fn test_parse_percent() {
+        assert_eq!(parse_percent("50").unwrap(), 50.0);
+        assert_eq!(parse_percent("99.5").unwrap(), 99.5);
+        assert_eq!(parse_percent("101").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(parse_percent("x").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+    }
+
+}
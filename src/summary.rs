@@ -0,0 +1,162 @@
+//! Summary statistics of a run.
+//!
+//! Most statistics come from the tracker and the duplicates at the end of
+//! the run. Skipped files and errors happen anywhere, on any thread, so we
+//! count them with the run's atomic counters, which the args own, via
+//! `Counters::skip` and via `error!`.
+//!
+//! The summary prints as text to stderr, so it never mixes with paths on
+//! stdout, or else is part of the structured output formats.
+
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use serde::Serialize;
+use crate::{FileLen, Tracker};
+use crate::action::Action;
+use crate::group::Duplicates;
+use crate::inode;
+
+/// Print an error to stderr, and count it in the run's counters for the summary.
+macro_rules! error {
+    ($counters:expr, $($arg:tt)*) => {{
+        $counters.count_error();
+        eprintln!($($arg)*);
+    }};
+}
+
+/// Counters of one run.
+#[derive(Debug, Default)]
+pub struct Counters {
+    files_skipped: AtomicUsize,
+    errors: AtomicUsize,
+}
+
+impl Counters {
+
+    /// Count one error.
+    pub fn count_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Count one file that is skipped by a filter, such as `--newer-than`.
+    pub fn skip(&self) {
+        self.files_skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+}
+
+/// Summary statistics of a run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Summary {
+    pub files_scanned: usize,
+    pub bytes_scanned: FileLen,
+    pub files_skipped: usize,
+    /// Count of file lengths that are shared by more than one file, which are compared.
+    pub size_buckets: usize,
+    pub duplicate_groups: usize,
+    pub clones: usize,
+    pub bytes_reclaimable: FileLen,
+    pub bytes_reclaimed: FileLen,
+    pub errors: usize,
+    pub seconds_scan: f64,
+    pub seconds_detect: f64,
+    pub seconds_act: f64,
+}
+
+impl Summary {
+
+    /// Summarize a run, from its counters, its tracker, its duplicates, and the timing of each stage.
+    pub fn new(counters: &Counters, tracker: &Tracker, duplicates: &Duplicates, scan: Duration, detect: Duration, act: Duration) -> Self {
+        Summary {
+            files_scanned: tracker.map.values().map(|paths| paths.len()).sum(),
+            bytes_scanned: tracker.map.iter().map(|(len, paths)| len * paths.len() as FileLen).sum(),
+            files_skipped: counters.files_skipped.load(Ordering::Relaxed),
+            size_buckets: tracker.map.values().filter(|paths| paths.len() > 1).count(),
+            duplicate_groups: duplicates.groups.len(),
+            clones: duplicates.groups.iter().map(|group| group.clones().count()).sum(),
+            bytes_reclaimable: duplicates.groups.iter().map(|group| group.bytes_reclaimable).sum(),
+            bytes_reclaimed: bytes_reclaimed(duplicates),
+            errors: counters.errors.load(Ordering::Relaxed),
+            seconds_scan: scan.as_secs_f64(),
+            seconds_detect: detect.as_secs_f64(),
+            seconds_act: act.as_secs_f64(),
+        }
+    }
+
+}
+
+/// Total the bytes of clones that an action succeeded on.
+///
+/// A clone that shares its inode with the original or with an earlier
+/// clone reclaims no bytes.
+fn bytes_reclaimed(duplicates: &Duplicates) -> FileLen {
+    duplicates.groups
+    .iter()
+    .map(|group| inode::bytes_reclaimable(
+        group.clones().filter(|member| member.action != Action::None && member.error.is_none()).map(|member| (member.id(), member.size)),
+        &group.original_ids(),
+    ))
+    .sum()
+}
+
+/// Write the summary as text.
+pub fn write_text<W: Write>(summary: &Summary, w: &mut W) -> io::Result<()> {
+    writeln!(w, "Summary:")?;
+    writeln!(w, "  files scanned: {}", summary.files_scanned)?;
+    writeln!(w, "  bytes scanned: {}", summary.bytes_scanned)?;
+    writeln!(w, "  files skipped: {}", summary.files_skipped)?;
+    writeln!(w, "  size buckets: {}", summary.size_buckets)?;
+    writeln!(w, "  duplicate groups: {}", summary.duplicate_groups)?;
+    writeln!(w, "  clones: {}", summary.clones)?;
+    writeln!(w, "  bytes reclaimable: {}", summary.bytes_reclaimable)?;
+    writeln!(w, "  bytes reclaimed: {}", summary.bytes_reclaimed)?;
+    writeln!(w, "  errors: {}", summary.errors)?;
+    writeln!(w, "  seconds scan: {:.3}", summary.seconds_scan)?;
+    writeln!(w, "  seconds detect: {:.3}", summary.seconds_detect)?;
+    writeln!(w, "  seconds act: {:.3}", summary.seconds_act)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    /// Test `Summary::new` via these files:
+    ///
+    /// * `a/alpha.txt` which contains `alpha`
+    /// * `a/bravo.txt` which contains `bravo`
+    /// * `b/alpha.txt` which contains `alpha`
+    /// * `b/alpha_2.txt` which contains `alpha`
+    ///
+    /// The duplicates are the test duplicates, where one clone is deleted,
+    /// and one clone has an error.
+    /// Must count only the deleted clone as reclaimed.
+    ///
+    fn test_summary_new() {
+        let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "detect_duplicates"].iter().collect();
+        let counters = Counters::default();
+        let mut tracker = Tracker::new();
+        for path in &["a/alpha.txt", "a/bravo.txt", "b/alpha.txt", "b/alpha_2.txt"] {
+            tracker.insert_path(&counters, dir.join(path));
+        }
+        counters.skip();
+        let mut duplicates = crate::group::duplicates();
+        duplicates.groups[0].members[1].action = Action::Delete;
+        duplicates.groups[0].members[2].action = Action::Delete;
+        duplicates.groups[0].members[2].error = Some(String::from("err"));
+        let summary = Summary::new(&counters, &tracker, &duplicates, Duration::from_secs(1), Duration::from_secs(2), Duration::from_secs(3));
+        assert_eq!(summary.files_scanned, 4);
+        assert_eq!(summary.bytes_scanned, 24);
+        assert_eq!(summary.files_skipped, 1);
+        assert_eq!(summary.size_buckets, 1);
+        assert_eq!(summary.duplicate_groups, 2);
+        assert_eq!(summary.clones, 3);
+        assert_eq!(summary.bytes_reclaimable, 14);
+        assert_eq!(summary.bytes_reclaimed, 6);
+        assert_eq!(summary.errors, 0);
+        assert_eq!(summary.seconds_detect, 2.0);
+    }
+
+}
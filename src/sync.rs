@@ -0,0 +1,266 @@
+//! Content-aware sync from a source directory to a destination directory.
+//!
+//! We build on the diff: a file whose content is nowhere in the destination
+//! is copied, even if it's changed at the same path. A file whose content
+//! is in the destination under another path is put in place by copying,
+//! or by hardlinking or reflinking the destination's file, which costs no
+//! read of the source and, for a link, no space. A file only in the
+//! destination is left as is.
+//!
+//! Each step is atomic: we create the file at a temporary path in the same
+//! directory, then rename it over the target.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use serde::Serialize;
+use serde_json::json;
+use crate::action;
+use crate::args::Args;
+use crate::diff::Diff;
+use crate::output::Format;
+
+/// How to put in place content that is in the destination under another path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Link {
+    Copy,
+    Hardlink,
+    Reflink,
+}
+
+impl Default for Link {
+    fn default() -> Self {
+        Link::Copy
+    }
+}
+
+impl FromStr for Link {
+    type Err = io::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "copy" => Ok(Link::Copy),
+            "hardlink" => Ok(Link::Hardlink),
+            "reflink" => Ok(Link::Reflink),
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("link:{}", s))),
+        }
+    }
+}
+
+/// One step of a sync: create `to` from `from`, by copying or linking.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Step {
+    pub link: Link,
+    pub from: PathBuf,
+    pub to: PathBuf,
+}
+
+/// Plan the steps to sync the source to the destination, from their diff.
+///
+/// Each target gets one step. Where a target is both changed and remapped
+/// from content in the destination, we keep the link step, which costs no
+/// read of the source.
+pub fn plan(diff: &Diff, link: Link) -> Vec<Step> {
+    let (src, dst) = (&diff.a, &diff.b);
+    let mut steps: Vec<Step> = Vec::new();
+    for path in diff.only_in_a.iter().chain(diff.changed.iter()) {
+        steps.push(Step { link: Link::Copy, from: src.join(path), to: dst.join(path) });
+    }
+    for (path, path_in_dst) in diff.identical.iter().filter(|(x, y)| x != y) {
+        match link {
+            Link::Copy => steps.push(Step { link: Link::Copy, from: src.join(path), to: dst.join(path) }),
+            _ => steps.push(Step { link: link, from: dst.join(path_in_dst), to: dst.join(path) }),
+        }
+    }
+    steps.sort_by(|x, y| x.to.cmp(&y.to).then_with(|| (x.link == Link::Copy).cmp(&(y.link == Link::Copy))));
+    steps.dedup_by(|x, y| x.to == y.to);
+    steps
+}
+
+/// Apply one step, atomically, and create its parent directories as needed.
+///
+/// A copy keeps the source's modification time. A reflink falls back to
+/// a copy where the file system can't reflink.
+pub fn apply(step: &Step) -> io::Result<()> {
+    if let Some(parent) = step.to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let tmp = action::tmp_path(&step.to)?;
+    let created = match step.link {
+        Link::Copy => copy(&step.from, &tmp),
+        Link::Hardlink => fs::hard_link(&step.from, &tmp),
+        Link::Reflink => reflink(&step.from, &tmp).or_else(|_| { let _ = fs::remove_file(&tmp); copy(&step.from, &tmp) }),
+    };
+    created.and_then(|_| fs::rename(&tmp, &step.to)).inspect_err(|_| { let _ = fs::remove_file(&tmp); })
+}
+
+/// Copy a file, and keep its modification time.
+fn copy(from: &Path, to: &Path) -> io::Result<()> {
+    fs::copy(from, to)?;
+    let modified = fs::metadata(from)?.modified()?;
+    File::options().write(true).open(to)?.set_modified(modified)
+}
+
+/// Reflink a file, i.e. clone its extents, via the FICLONE ioctl.
+#[cfg(target_os = "linux")]
+fn reflink(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+    const FICLONE: u64 = 0x40049409;
+    let source = File::open(from)?;
+    let target = File::create(to)?;
+    let ret = unsafe { libc::ioctl(target.as_raw_fd(), FICLONE as _, source.as_raw_fd()) };
+    if ret != 0 { return Err(io::Error::last_os_error()) }
+    Ok(())
+}
+
+/// Reflink a file; unsupported on this platform.
+#[cfg(not(target_os = "linux"))]
+fn reflink(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Other, "reflink: unsupported"))
+}
+
+/// Sync the source to the destination: plan the steps, then apply each,
+/// unless `--print`, which is a dry run.
+///
+/// Return the steps, each with its error if any.
+pub fn sync(args: &Args, diff: &Diff, link: Link) -> Vec<(Step, Option<String>)> {
+    plan(diff, link)
+    .into_iter()
+    .map(|step| {
+        if args.print { return (step, None) }
+        let error = match apply(&step) {
+            Ok(()) => None,
+            Err(e) => { error!(args.counters, "err:{} {:?}:{:?}", e, step.link, step.to); Some(e.to_string()) },
+        };
+        (step, error)
+    })
+    .collect()
+}
+
+/// Write the steps, as one JSON array, or as NDJSON with one step per line,
+/// or else as text.
+pub fn write_steps<W: Write>(args: &Args, steps: &[(Step, Option<String>)], w: &mut W) -> io::Result<()> {
+    let values = steps
+    .iter()
+    .map(|(step, error)| json!({ "link": step.link, "from": step.from.to_string_lossy(), "to": step.to.to_string_lossy(), "error": error }));
+    match args.format {
+        Format::Json => {
+            serde_json::to_writer_pretty(&mut *w, &values.collect::<Vec<serde_json::Value>>())?;
+            writeln!(w)
+        },
+        Format::Ndjson => {
+            for value in values {
+                serde_json::to_writer(&mut *w, &value)?;
+                writeln!(w)?;
+            }
+            Ok(())
+        },
+        _ => {
+            for (step, _) in steps.iter() {
+                let verb = match step.link {
+                    Link::Copy => "Copy",
+                    Link::Hardlink => "Hardlink",
+                    Link::Reflink => "Reflink",
+                };
+                writeln!(w, "{}: {:?} {:?}", verb, step.from, step.to)?;
+            }
+            Ok(())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+
+    /// Copy the `test/diff` directories to a temp dir, and return (src, dst).
+    fn setup(name: &str) -> (PathBuf, PathBuf) {
+        let dir = std::env::temp_dir().join("deduplicate-files-test-sync").join(name);
+        let _ = fs::remove_dir_all(&dir);
+        let fixture: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "diff"].iter().collect();
+        for path in &["a/alpha.txt", "a/bravo.txt", "a/charlie.txt", "a/delta.txt", "b/alpha.txt", "b/renamed/bravo_2.txt", "b/charlie.txt", "b/echo.txt"] {
+            let to = dir.join(path);
+            fs::create_dir_all(to.parent().unwrap()).unwrap();
+            fs::copy(fixture.join(path), to).unwrap();
+        }
+        (dir.join("a"), dir.join("b"))
+    }
+
+    #[test]
+    /// Test `sync` with `Link::Hardlink`, via the `test/diff` files.
+    /// Must copy missing and changed content, hardlink content that is in
+    /// the destination under another path, and keep files only in the destination.
+    ///
+    fn test_sync_x_hardlink() {
+        let (src, dst) = setup("hardlink");
+        let args = Args::default();
+        let diff = crate::diff::diff(&args, &src, &dst);
+        let steps = sync(&args, &diff, Link::Hardlink);
+        assert_eq!(steps.iter().map(|(step, _)| (step.link, step.to.clone())).collect::<Vec<(Link, PathBuf)>>(), vec![
+            (Link::Hardlink, dst.join("bravo.txt")),
+            (Link::Copy, dst.join("charlie.txt")),
+            (Link::Copy, dst.join("delta.txt")),
+        ]);
+        assert!(steps.iter().all(|(_, error)| error.is_none()));
+        assert_eq!(fs::metadata(dst.join("bravo.txt")).unwrap().ino(), fs::metadata(dst.join("renamed/bravo_2.txt")).unwrap().ino());
+        assert_eq!(fs::read_to_string(dst.join("charlie.txt")).unwrap(), "charlie\n");
+        assert_eq!(fs::read_to_string(dst.join("delta.txt")).unwrap(), "delta\n");
+        assert!(dst.join("echo.txt").exists());
+        assert_eq!(crate::diff::diff(&args, &src, &dst).only_in_a.len(), 0);
+    }
+
+    #[test]
+    /// Test `plan` with a path that is both changed and the target of a remap.
+    /// Must plan one step for the path, which is the link step.
+    ///
+    fn test_plan_x_changed_and_identical() {
+        let diff = Diff {
+            a: PathBuf::from("a"),
+            b: PathBuf::from("b"),
+            identical: vec![(PathBuf::from("alpha.txt"), PathBuf::from("renamed/alpha.txt"))],
+            changed: vec![PathBuf::from("alpha.txt")],
+            ..Diff::default()
+        };
+        assert_eq!(plan(&diff, Link::Hardlink), vec![
+            Step { link: Link::Hardlink, from: PathBuf::from("b/renamed/alpha.txt"), to: PathBuf::from("b/alpha.txt") },
+        ]);
+        assert_eq!(plan(&diff, Link::Copy), vec![
+            Step { link: Link::Copy, from: PathBuf::from("a/alpha.txt"), to: PathBuf::from("b/alpha.txt") },
+        ]);
+    }
+
+    #[test]
+    /// Test `sync` with `--print`.
+    /// Must plan the steps, yet not apply them.
+    ///
+    fn test_sync_x_print() {
+        let (src, dst) = setup("print");
+        let args = Args { print: true, ..Args::default() };
+        let diff = crate::diff::diff(&args, &src, &dst);
+        assert_eq!(sync(&args, &diff, Link::Copy).len(), 3);
+        assert!(!dst.join("delta.txt").exists());
+    }
+
+    #[test]
+    /// Test `write_steps` with `Format::Ndjson`.
+    /// Must write one compact step per line.
+    ///
+    fn test_write_steps_x_ndjson() {
+        let steps = vec![
+            (Step { link: Link::Hardlink, from: PathBuf::from("b/renamed/bravo_2.txt"), to: PathBuf::from("b/bravo.txt") }, None),
+            (Step { link: Link::Copy, from: PathBuf::from("a/delta.txt"), to: PathBuf::from("b/delta.txt") }, Some(String::from("denied"))),
+        ];
+        let mut w: Vec<u8> = Vec::new();
+        write_steps(&Args { format: Format::Ndjson, ..Args::default() }, &steps, &mut w).unwrap();
+        let s = String::from_utf8(w).unwrap();
+        let values: Vec<serde_json::Value> = s.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(values, vec![
+            json!({ "link": "hardlink", "from": "b/renamed/bravo_2.txt", "to": "b/bravo.txt", "error": null }),
+            json!({ "link": "copy", "from": "a/delta.txt", "to": "b/delta.txt", "error": "denied" }),
+        ]);
+    }
+
+}
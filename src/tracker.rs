@@ -0,0 +1,62 @@
+//! Tracker of scanned files, by file length.
+//!
+//! Files can be duplicates only if they have the same length, so we track
+//! each path in a bucket by its length, and compare only within buckets.
+//!
+//! We also track each path's canonical form, so one directory entry is
+//! tracked once, even if the walk reaches it by two spellings, such as via
+//! overlapping roots, `.`, or a symlinked directory.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use crate::{FileLen, SetOfPathBuf};
+use crate::summary::Counters;
+
+/// Map of file length to the set of paths of that length.
+#[derive(Clone, Debug, Default)]
+pub struct Tracker {
+    pub map: HashMap<FileLen, SetOfPathBuf>,
+    seen: HashSet<PathBuf>,
+}
+
+impl Tracker {
+
+    /// Create an empty tracker.
+    pub fn new() -> Self {
+        Tracker::default()
+    }
+
+    /// Track a path with a known length.
+    pub fn insert(&mut self, len: FileLen, path: PathBuf) {
+        self.map.entry(len).or_default().insert(path);
+    }
+
+    /// Track a path, by reading its length, and count any error.
+    ///
+    /// A symlink is resolved, so we track its target, and never act on the
+    /// link as if it were content. A path whose canonical form is already
+    /// tracked is skipped.
+    pub fn insert_path(&mut self, counters: &Counters, path: PathBuf) {
+        let canonical = match fs::canonicalize(&path) {
+            Ok(canonical) => canonical,
+            Err(e) => { error!(counters, "err:{} canonicalize:{:?}", e, path); return },
+        };
+        if !self.seen.insert(canonical.clone()) { return }
+        let path = match fs::symlink_metadata(&path) {
+            Ok(metadata) => if metadata.file_type().is_symlink() { canonical } else { path },
+            Err(e) => { error!(counters, "err:{} symlink_metadata:{:?}", e, path); return },
+        };
+        match fs::symlink_metadata(&path) {
+            Ok(metadata) => self.insert(metadata.len(), path),
+            Err(e) => error!(counters, "err:{} symlink_metadata:{:?}", e, path),
+        }
+    }
+
+    /// Is a path tracked?
+    #[cfg(test)]
+    pub fn contains_path(&self, path: &PathBuf) -> bool {
+        self.map.values().any(|paths| paths.contains(path))
+    }
+
+}
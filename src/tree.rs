@@ -0,0 +1,345 @@
+//! Duplicate directories, by tree digests.
+//!
+//! Each directory gets a Merkle-style digest, from the name, the kind,
+//! and the digest of each child, so two directories have equal digests
+//! when their whole subtrees are identical. A file's digest is its
+//! content digest, which we know for every file that is in a group.
+//! A directory that holds a unique file can't have a duplicate, so it
+//! gets no digest, and nor do its ancestors.
+//!
+//! With `--directories-ignore-names`, a directory's digest comes from the
+//! multiset of its files' content digests only, regardless of names or
+//! layout, so a renamed or reorganized copy matches too. The multiset
+//! digest is a sum of the file digests, lane by lane, which a directory
+//! computes from its children's sums without holding every digest.
+//!
+//! We see only directories that hold files that we scan, within the path
+//! args, so an empty subdirectory doesn't count.
+//!
+//! We report the outermost duplicate directories, and a directory under a
+//! clone directory is in no other group. Actions apply to the file groups,
+//! so every file under a clone directory is acted on. After acting, we
+//! collapse the file groups for reporting: each file under a clone
+//! directory is left out of its file group, because its directory's group
+//! covers it.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ffi::OsString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use crate::{FileLen, Tracker};
+use crate::args::Args;
+use crate::digest::Digest;
+use crate::group::{DirectoryGroup, DirectoryMember, DuplicateGroup, Duplicates, Role};
+use crate::inode;
+
+/// A node of a tree: its digest, its total bytes, its count of files,
+/// and the sum of its files' content digests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Node {
+    pub digest: Digest,
+    pub size: FileLen,
+    pub files: usize,
+    pub sum: [u64; 4],
+}
+
+impl Node {
+
+    /// Create the node of a file from its content digest.
+    pub fn file(digest: Digest, size: FileLen) -> Self {
+        let mut sum = [0u64; 4];
+        for (lane, bytes) in sum.iter_mut().zip(digest.as_bytes().chunks(8)) {
+            let mut lane_bytes = [0u8; 8];
+            lane_bytes.copy_from_slice(bytes);
+            *lane = u64::from_le_bytes(lane_bytes);
+        }
+        Node { digest: digest, size: size, files: 1, sum: sum }
+    }
+
+}
+
+/// A child of a directory, which is a file or a subdirectory.
+enum Child {
+    File(Option<Node>),
+    Directory(Option<Node>),
+}
+
+/// Is a directory within the path args or `--reference` roots? With neither, every directory is.
+pub(crate) fn is_within_paths(args: &Args, dir: &Path) -> bool {
+    !dir.as_os_str().is_empty() && (
+        (args.paths.is_empty() && args.reference.is_empty())
+        || args.paths.iter().chain(args.reference.iter()).any(|root| dir.starts_with(root))
+    )
+}
+
+/// Compute the digest of each directory that holds scanned files.
+///
+/// A directory that holds a file with no content digest, or a subdirectory
+/// with no digest, maps to None.
+pub fn directory_nodes(args: &Args, tracker: &Tracker, duplicates: &Duplicates) -> HashMap<PathBuf, Option<Node>> {
+    let digests: HashMap<&Path, (Digest, FileLen)> = duplicates.groups
+    .iter()
+    .flat_map(|group| group.members.iter().map(move |member| (member.path.as_path(), (group.digest, group.size))))
+    .collect();
+    let mut children: HashMap<PathBuf, BTreeMap<OsString, Child>> = HashMap::new();
+    for path in tracker.map.values().flatten() {
+        let (dir, name) = match (path.parent(), path.file_name()) {
+            (Some(dir), Some(name)) if is_within_paths(args, dir) => (dir, name),
+            _ => continue,
+        };
+        let node = digests.get(path.as_path()).map(|(digest, size)| Node::file(*digest, *size));
+        children.entry(dir.to_path_buf()).or_default().insert(name.to_os_string(), Child::File(node));
+        for ancestor in dir.ancestors().skip(1).filter(|ancestor| is_within_paths(args, ancestor)) {
+            children.entry(ancestor.to_path_buf()).or_default();
+        }
+    }
+    let mut dirs: Vec<PathBuf> = children.keys().cloned().collect();
+    dirs.sort_by_key(|dir| std::cmp::Reverse(dir.components().count()));
+    let mut nodes: HashMap<PathBuf, Option<Node>> = HashMap::new();
+    for dir in dirs {
+        let node = directory_node(children.get(&dir).unwrap(), args.directories_ignore_names);
+        if let (Some(parent), Some(name)) = (dir.parent(), dir.file_name()) {
+            if let Some(siblings) = children.get_mut(parent) {
+                siblings.insert(name.to_os_string(), Child::Directory(node));
+            }
+        }
+        nodes.insert(dir, node);
+    }
+    nodes
+}
+
+/// Compute the node of a directory from its children, in name order,
+/// or from only the sum of its files' content digests if ignoring names.
+fn directory_node(children: &BTreeMap<OsString, Child>, ignore_names: bool) -> Option<Node> {
+    let mut hasher = blake3::Hasher::new();
+    let mut size: FileLen = 0;
+    let mut files: usize = 0;
+    let mut sum = [0u64; 4];
+    for (name, child) in children.iter() {
+        let (kind, node): (&[u8], &Option<Node>) = match child {
+            Child::File(node) => (b"f", node),
+            Child::Directory(node) => (b"d", node),
+        };
+        let node = node.as_ref()?;
+        hasher.update(kind);
+        hasher.update(name.as_bytes());
+        hasher.update(b"\0");
+        hasher.update(node.digest.as_bytes());
+        size += node.size;
+        files += node.files;
+        for (lane, x) in sum.iter_mut().zip(node.sum.iter()) {
+            *lane = lane.wrapping_add(*x);
+        }
+    }
+    if files == 0 { return None }
+    let digest = match ignore_names {
+        true => {
+            let mut hasher = blake3::Hasher::new();
+            hasher.update(&(files as u64).to_le_bytes());
+            for lane in sum.iter() {
+                hasher.update(&lane.to_le_bytes());
+            }
+            hasher.finalize()
+        },
+        false => hasher.finalize(),
+    };
+    Some(Node { digest: digest, size: size, files: files, sum: sum })
+}
+
+/// Detect the outermost duplicate directories.
+///
+/// A directory group is left out when its directories are in distinct
+/// parents that are duplicates of each other, because the parents'
+/// group covers it. A directory within another directory of its group,
+/// which can match when ignoring names, is left out of the group, and so
+/// is a directory within a clone directory of a bigger group, so no
+/// directory is in two groups.
+///
+/// The file groups are left as is, for acting; see `collapse`.
+pub fn detect_duplicate_directories(args: &Args, tracker: &Tracker, duplicates: &mut Duplicates) {
+    let nodes = directory_nodes(args, tracker, duplicates);
+    let mut by_digest: HashMap<Digest, Vec<&PathBuf>> = HashMap::new();
+    for (dir, node) in nodes.iter() {
+        if let Some(node) = node {
+            by_digest.entry(node.digest).or_default().push(dir);
+        }
+    }
+    by_digest.retain(|_, dirs| dirs.len() > 1);
+    let parent_digest = |dir: &Path| dir.parent().and_then(|parent| nodes.get(parent).cloned().flatten()).map(|node| node.digest);
+    let mut candidates: Vec<(Digest, Vec<&PathBuf>)> = by_digest.iter().map(|(digest, dirs)| (*digest, dirs.clone())).collect();
+    candidates.sort_by_key(|(digest, dirs)| {
+        let depth = dirs.iter().map(|dir| dir.components().count()).min();
+        (std::cmp::Reverse(nodes[dirs[0]].unwrap().size), depth, *digest.as_bytes())
+    });
+    let mut groups: Vec<DirectoryGroup> = Vec::new();
+    let mut claimed: Vec<PathBuf> = Vec::new();
+    for (digest, mut dirs) in candidates {
+        let all = dirs.clone();
+        dirs.retain(|dir| !all.iter().any(|other| other != dir && dir.starts_with(other)));
+        dirs.retain(|dir| !claimed.iter().any(|clone| dir.starts_with(clone)));
+        if dirs.len() < 2 { continue }
+        let parents: HashSet<&Path> = dirs.iter().filter_map(|dir| dir.parent()).collect();
+        let parent_digests: HashSet<Option<Digest>> = dirs.iter().map(|dir| parent_digest(dir)).collect();
+        if parents.len() == dirs.len() && parent_digests.len() == 1 {
+            if let Some(Some(parent_digest)) = parent_digests.iter().next() {
+                if by_digest.contains_key(parent_digest) { continue }
+            }
+        }
+        dirs.sort_by_key(|dir| crate::rank(args, dir));
+        let originals = std::cmp::max(1, dirs.iter().filter(|dir| crate::is_reference(args, dir)).count());
+        if originals == dirs.len() { continue }
+        claimed.extend(dirs.iter().skip(originals).map(|dir| dir.to_path_buf()));
+        let node = nodes[dirs[0]].unwrap();
+        groups.push(DirectoryGroup {
+            digest: digest,
+            size: node.size,
+            files: node.files,
+            bytes_reclaimable: node.size * (dirs.len() - originals) as FileLen,
+            members: dirs.iter().enumerate().map(|(i, dir)| DirectoryMember {
+                path: dir.to_path_buf(),
+                role: if i < originals { Role::Original } else { Role::Clone },
+            }).collect(),
+        });
+    }
+    groups.sort_by(|a, b| a.size.cmp(&b.size).then_with(|| a.original().cmp(&b.original())));
+    duplicates.directories = groups;
+}
+
+/// Collapse the file groups under the duplicate directories, for reporting,
+/// so call this after acting: each file under a clone directory is left out
+/// of its file group, and a group with one member left is left out.
+pub fn collapse(duplicates: &mut Duplicates) {
+    let clones: HashSet<&Path> = duplicates.directories
+    .iter()
+    .flat_map(|group| group.members.iter().filter(|member| member.role == Role::Clone).map(|member| member.path.as_path()))
+    .collect();
+    for group in duplicates.groups.iter_mut() {
+        group.members.retain(|member| !member.path.ancestors().any(|ancestor| clones.contains(ancestor)));
+        reassess(group);
+    }
+    duplicates.groups.retain(|group| group.members.len() > 1);
+}
+
+/// Reassess a file group after members are left out: ensure it has an
+/// original, and recompute its bytes reclaimable.
+fn reassess(group: &mut DuplicateGroup) {
+    if !group.members.iter().any(|member| member.role == Role::Original) {
+        if let Some(member) = group.members.first_mut() {
+            member.role = Role::Original;
+        }
+    }
+    group.bytes_reclaimable = inode::bytes_reclaimable(group.clones().map(|member| (member.id(), member.size)), &group.original_ids());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    /// Test `detect_duplicate_directories` via these files:
+    ///
+    /// * `a/alpha.txt` which contains `alpha`
+    /// * `a/x/bravo.txt` which contains `bravo`
+    /// * `b/alpha.txt` which contains `alpha`
+    /// * `b/x/bravo.txt` which contains `bravo`
+    /// * `c/alpha.txt` which contains `alpha`
+    /// * `c/charlie.txt` which contains `charlie`
+    ///
+    /// Must group `a` and `b`, yet not `a/x` and `b/x`, which `a` and `b` cover,
+    /// and must keep the files under `b` in the file groups, for acting,
+    /// until `collapse` leaves them out.
+    ///
+    fn test_detect_duplicate_directories() {
+        let dir: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "tree"].iter().collect();
+        let args = Args { paths: vec![dir.clone()], ..Args::default() };
+        let mut tracker = Tracker::new();
+        crate::on_paths(&args, &mut tracker, &args.paths);
+        let mut duplicates = crate::detect_duplicates(&args, &tracker);
+        detect_duplicate_directories(&args, &tracker, &mut duplicates);
+        assert_eq!(duplicates.directories.len(), 1);
+        let group = &duplicates.directories[0];
+        assert_eq!(group.original(), Some(&dir.join("a")));
+        assert_eq!(group.members[1].path, dir.join("b"));
+        assert_eq!(group.files, 2);
+        assert_eq!(group.size, 12);
+        assert_eq!(group.bytes_reclaimable, 12);
+        assert_eq!(duplicates.groups.len(), 2);
+        collapse(&mut duplicates);
+        assert_eq!(duplicates.groups.len(), 1);
+        assert_eq!(
+            duplicates.groups[0].members.iter().map(|member| member.path.clone()).collect::<Vec<PathBuf>>(),
+            vec![dir.join("a").join("alpha.txt"), dir.join("c").join("alpha.txt")]
+        );
+        assert_eq!(duplicates.groups[0].bytes_reclaimable, 6);
+    }
+
+    #[test]
+    /// Test `detect_duplicate_directories` via a temp dir with these files:
+    ///
+    /// * `a/alpha.txt` which contains `alpha`
+    /// * `a/x/bravo.txt` which contains `bravo`
+    /// * `b/alpha.txt` which contains `alpha`
+    /// * `b/x/bravo.txt` which contains `bravo`
+    /// * `c/bravo.txt` which contains `bravo`
+    ///
+    /// Must group `a` and `b`, then `a/x` and `c`, yet not `b/x`, which is
+    /// within the clone directory `b`, so no directory is in two groups.
+    ///
+    fn test_detect_duplicate_directories_x_nested() {
+        let dir = std::env::temp_dir().join("deduplicate-files-test-tree-nested");
+        let _ = std::fs::remove_dir_all(&dir);
+        for (path, content) in &[("a/alpha.txt", "alpha\n"), ("a/x/bravo.txt", "bravo\n"), ("b/alpha.txt", "alpha\n"), ("b/x/bravo.txt", "bravo\n"), ("c/bravo.txt", "bravo\n")] {
+            std::fs::create_dir_all(dir.join(path).parent().unwrap()).unwrap();
+            std::fs::write(dir.join(path), content).unwrap();
+        }
+        let args = Args { paths: vec![dir.clone()], ..Args::default() };
+        let mut tracker = Tracker::new();
+        crate::on_paths(&args, &mut tracker, &args.paths);
+        let mut duplicates = crate::detect_duplicates(&args, &tracker);
+        detect_duplicate_directories(&args, &tracker, &mut duplicates);
+        let groups: Vec<Vec<PathBuf>> = duplicates.directories
+        .iter()
+        .map(|group| group.members.iter().map(|member| member.path.clone()).collect())
+        .collect();
+        assert_eq!(groups, vec![
+            vec![dir.join("a").join("x"), dir.join("c")],
+            vec![dir.join("a"), dir.join("b")],
+        ]);
+    }
+
+    #[test]
+    /// Test `directory_node` with a renamed file.
+    /// Must differ, because names count.
+    ///
+    fn test_directory_node_x_names() {
+        let node = Node::file(blake3::hash(b"alpha\n"), 6);
+        let mut a: BTreeMap<OsString, Child> = BTreeMap::new();
+        a.insert(OsString::from("alpha.txt"), Child::File(Some(node)));
+        let mut b: BTreeMap<OsString, Child> = BTreeMap::new();
+        b.insert(OsString::from("alpha_2.txt"), Child::File(Some(node)));
+        assert_ne!(directory_node(&a, false).unwrap().digest, directory_node(&b, false).unwrap().digest);
+        b.insert(OsString::from("bravo.txt"), Child::File(None));
+        assert_eq!(directory_node(&b, false), None);
+    }
+
+    #[test]
+    /// Test `directory_node` ignoring names, with a renamed and moved file.
+    /// Must be equal, because only the multiset of contents counts.
+    ///
+    fn test_directory_node_x_ignore_names() {
+        let alpha = Node::file(blake3::hash(b"alpha\n"), 6);
+        let bravo = Node::file(blake3::hash(b"bravo\n"), 6);
+        let mut sub: BTreeMap<OsString, Child> = BTreeMap::new();
+        sub.insert(OsString::from("bravo_2.txt"), Child::File(Some(bravo)));
+        let mut a: BTreeMap<OsString, Child> = BTreeMap::new();
+        a.insert(OsString::from("alpha.txt"), Child::File(Some(alpha)));
+        a.insert(OsString::from("bravo.txt"), Child::File(Some(bravo)));
+        let mut b: BTreeMap<OsString, Child> = BTreeMap::new();
+        b.insert(OsString::from("photo.txt"), Child::File(Some(alpha)));
+        b.insert(OsString::from("x"), Child::Directory(directory_node(&sub, true)));
+        assert_eq!(directory_node(&a, true).unwrap().digest, directory_node(&b, true).unwrap().digest);
+        b.insert(OsString::from("photo_2.txt"), Child::File(Some(alpha)));
+        assert_ne!(directory_node(&a, true).unwrap().digest, directory_node(&b, true).unwrap().digest);
+    }
+
+}
@@ -0,0 +1,169 @@
+//! Directory walking.
+//!
+//! We use the `jwalk` crate, which reads directories in parallel on the
+//! rayon pool, so even one root is walked by `--jobs` threads, and which
+//! sorts each directory, so the walk order is deterministic. It's
+//! configured by our args: follow symlinks (with loop detection), stay on
+//! one file system, limit the min depth and max depth, and skip hidden
+//! dot-files, though never the walk root, so a user can walk e.g. `~/.config`.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use jwalk::WalkDirGeneric;
+use crate::args::Args;
+
+/// The (dev, inode) of each directory from the walk root down to the
+/// directory being read, so a followed symlink back to any of them is a loop.
+type Ancestors = Vec<(u64, u64)>;
+
+/// A walk entry.
+pub type DirEntry = jwalk::DirEntry<(Ancestors, ())>;
+
+/// Create a walker for a path, configured by the args.
+///
+/// A `--reference` root below the walk root is skipped, because it's
+/// walked on its own, so its files are read once, and only as references.
+///
+/// When following symlinks, a directory whose (dev, inode) is one of its
+/// ancestors is a loop, and isn't descended. We can't rely on jwalk for
+/// this, because it compares the link's raw target with the ancestor
+/// paths, so it misses a relative link such as `l -> ..`.
+pub fn walker<T: AsRef<Path>>(args: &Args, path: T) -> impl Iterator<Item = DirEntry> {
+    let mut walk_dir = WalkDirGeneric::<(Ancestors, ())>::new(path.as_ref())
+    .sort(true)
+    .follow_links(args.follow_symlinks)
+    .skip_hidden(args.skip_hidden);
+    if let Some(depth) = args.min_depth { walk_dir = walk_dir.min_depth(depth); }
+    if let Some(depth) = args.max_depth { walk_dir = walk_dir.max_depth(depth); }
+    let dev = if args.one_file_system { device_of(path.as_ref()) } else { None };
+    let root = fs::canonicalize(path.as_ref()).ok();
+    let skip: Vec<PathBuf> = args.reference
+    .iter()
+    .filter_map(|x| fs::canonicalize(x).ok())
+    .filter(|x| Some(x) != root.as_ref())
+    .collect();
+    let follow_symlinks = args.follow_symlinks;
+    let counters = args.counters.clone();
+    if dev.is_some() || !skip.is_empty() || follow_symlinks {
+        let counters = counters.clone();
+        walk_dir = walk_dir.process_read_dir(move |depth, parent, ancestors, children| {
+            if follow_symlinks && depth.is_some() {
+                if let Some(id) = id_of(parent) { ancestors.push(id); }
+            }
+            for child in children.iter_mut().flatten() {
+                if child.read_children_path.is_none() { continue }
+                if dev.is_some() && device_of(&child.path()) != dev {
+                    child.read_children_path = None;
+                }
+                if !skip.is_empty() && fs::canonicalize(child.path()).is_ok_and(|x| skip.contains(&x)) {
+                    child.read_children_path = None;
+                }
+                if follow_symlinks && child.read_children_path.is_some() && id_of(&child.path()).is_some_and(|id| ancestors.contains(&id)) {
+                    error!(counters, "err:symlink loop path:{:?}", child.path());
+                    child.read_children_path = None;
+                }
+            }
+        });
+    }
+    walk_dir
+    .into_iter()
+    .filter_map(move |e| match e {
+        Ok(entry) => Some(entry),
+        Err(err) => {
+            match err.loop_ancestor() {
+                Some(ancestor) => error!(counters, "err:symlink loop path:{:?} ancestor:{:?}", err.path(), ancestor),
+                None => error!(counters, "err:{}", err),
+            }
+            None
+        }
+    })
+}
+
+/// Get the device of a path, following symlinks, as the walk does.
+fn device_of(path: &Path) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|metadata| metadata.dev())
+}
+
+/// Get the (dev, inode) of a path, following symlinks, as the walk does.
+fn id_of(path: &Path) -> Option<(u64, u64)> {
+    use std::os::unix::fs::MetadataExt;
+    fs::metadata(path).ok().map(|metadata| (metadata.dev(), metadata.ino()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Walk the test directory and collect the file names.
+    fn file_names(args: &Args) -> Vec<String> {
+        let path: PathBuf = [env!("CARGO_MANIFEST_DIR"), "test", "walk"].iter().collect();
+        let mut names: Vec<String> = walker(args, path)
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.file_name().to_string_lossy().into_owned())
+        .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    /// Test `walker` with defaults, via these files:
+    ///
+    /// * `alpha.txt`
+    /// * `.hidden.txt`
+    /// * `a/bravo.txt`
+    ///
+    fn test_walker_x_default() {
+        let args = Args::default();
+        assert_eq!(file_names(&args), vec![".hidden.txt", "alpha.txt", "bravo.txt"]);
+    }
+
+    #[test]
+    /// Test `walker` with `--skip-hidden`.
+    /// Must skip the dot-file.
+    ///
+    fn test_walker_x_skip_hidden() {
+        let args = Args { skip_hidden: true, ..Args::default() };
+        assert_eq!(file_names(&args), vec!["alpha.txt", "bravo.txt"]);
+    }
+
+    #[test]
+    /// Test `walker` with `--max-depth 1`.
+    /// Must skip the subdirectory file.
+    ///
+    fn test_walker_x_max_depth() {
+        let args = Args { max_depth: Some(1), ..Args::default() };
+        assert_eq!(file_names(&args), vec![".hidden.txt", "alpha.txt"]);
+    }
+
+    #[test]
+    /// Test `walker` with `--min-depth 2`.
+    /// Must skip the top directory files.
+    ///
+    fn test_walker_x_min_depth() {
+        let args = Args { min_depth: Some(2), ..Args::default() };
+        assert_eq!(file_names(&args), vec!["bravo.txt"]);
+    }
+
+    #[test]
+    /// Test `walker` with `--follow-symlinks`, via a temp dir with these files:
+    ///
+    /// * `a/alpha.txt`
+    /// * `a/l1` which is a symlink to `..`
+    /// * `a/l2` which is a symlink to `..`
+    ///
+    /// Must walk each file once, and not descend either loop.
+    ///
+    fn test_walker_x_follow_symlinks_x_loop() {
+        let dir = std::env::temp_dir().join("deduplicate-files-test-walk-loop");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("a")).unwrap();
+        fs::write(dir.join("a/alpha.txt"), "alpha\n").unwrap();
+        std::os::unix::fs::symlink("..", dir.join("a/l1")).unwrap();
+        std::os::unix::fs::symlink("..", dir.join("a/l2")).unwrap();
+        let args = Args { follow_symlinks: true, ..Args::default() };
+        let files: Vec<PathBuf> = walker(&args, &dir).filter(|e| e.file_type().is_file()).map(|e| e.path()).collect();
+        assert_eq!(files, vec![dir.join("a/alpha.txt")]);
+    }
+
+}